@@ -7,7 +7,61 @@ extern crate cpython;
 
 mod parser;
 
-use cpython::{PyDict, PyErr, PyModule, PyResult, PyTuple, Python};
+use std::io::IsTerminal;
+
+use cpython::{
+    PyDict, PyErr, PyList, PyModule, PyObject, PyResult, Python, PythonObject, ToPyObject,
+};
+
+// Adds a function taking its own parameters followed by the arguments every parse function
+// shares. `$body` is given the interpreter and those arguments under the names it picks, and can
+// be left as just a parse function taking the file's path.
+macro_rules! add_parse_fn {
+    (
+        $py:ident,
+        $module:ident,
+        $name:expr,
+        |$python:ident, $arguments:ident $(, $parameter:ident: $type:ty)*| $body:expr
+    ) => {{
+        #[allow(clippy::too_many_arguments)]
+        fn function(
+            $python: Python,
+            $($parameter: $type,)*
+            modules: Vec<String>,
+            defines: Vec<String>,
+            target: &str,
+            colour: bool,
+            warnings_as_errors: bool,
+            signatures: Option<String>,
+        ) -> PyResult<PyObject> {
+            let $arguments =
+                &Arguments::new(modules, defines, target, colour, warnings_as_errors, signatures);
+            $body
+        }
+
+        $module.add(
+            $py,
+            $name,
+            py_fn!(
+                $py,
+                function(
+                    $($parameter: $type,)*
+                    modules: Vec<String>,
+                    defines: Vec<String> = vec![],
+                    target: &str = "warband",
+                    colour: bool = false,
+                    warnings_as_errors: bool = false,
+                    signatures: Option<String> = None
+                )
+            ),
+        )?;
+    }};
+    ($py:ident, $module:ident, $name:expr, $parse:path) => {
+        add_parse_fn!($py, $module, $name, |py, arguments, path: String| {
+            parse_file_py(py, &path, arguments, $parse)
+        });
+    };
+}
 
 py_module_initializer!(
     mb_ext_script,
@@ -20,25 +74,127 @@ py_module_initializer!(
             "This module parses and maps .mbs script files as an extension to the M&B modsys.",
         )?;
         m.add(py, "version", py_fn!(py, version_py()))?;
+        parser::add_exceptions(py, m)?;
+        add_parse_fn!(py, m, "parse", |py, arguments, path: String, name: String| {
+            parse_file_py(py, &path, arguments, |py, path, globals, options| {
+                parser::parse(py, path, &name, globals, options)
+            })
+        });
+        add_parse_fn!(py, m, "parse_triggers", parser::parse_triggers);
+        add_parse_fn!(py, m, "parse_simple_triggers", parser::parse_simple_triggers);
+        add_parse_fn!(py, m, "parse_mission_triggers", parser::parse_mission_triggers);
+        add_parse_fn!(py, m, "parse_dialogs", parser::parse_dialogs);
+        add_parse_fn!(py, m, "parse_menus", parser::parse_menus);
+        add_parse_fn!(py, m, "parse_presentations", parser::parse_presentations);
+        add_parse_fn!(py, m, "parse_scene_prop_triggers", parser::parse_scene_prop_triggers);
+        add_parse_fn!(py, m, "parse_item_triggers", parser::parse_item_triggers);
+        add_parse_fn!(
+            py,
+            m,
+            "apply_patches",
+            |py, arguments, scripts: PyList, paths: Vec<String>| {
+                apply_patches(py, scripts, &paths, arguments)
+            }
+        );
         m.add(
             py,
-            "parse",
-            py_fn!(py, parse_py(path: &str, name: &str, modules: Vec<String>)),
+            "finish_project",
+            py_fn!(py, finish_project_py(colour: bool = false, warnings_as_errors: bool = false)),
         )?;
         Ok(())
     }
 );
 
+// The arguments every parse function takes after its own.
+struct Arguments<'a> {
+    modules: Vec<String>,
+    defines: Vec<String>,
+    target: &'a str,
+    colour: bool,
+    warnings_as_errors: bool,
+    signatures: Option<String>, // Path to a file of signatures overriding the built in ones
+}
+
+impl<'a> Arguments<'a> {
+    fn new(
+        modules: Vec<String>,
+        defines: Vec<String>,
+        target: &'a str,
+        colour: bool,
+        warnings_as_errors: bool,
+        signatures: Option<String>,
+    ) -> Self {
+        Arguments {
+            modules,
+            defines,
+            target,
+            colour,
+            warnings_as_errors,
+            signatures,
+        }
+    }
+}
+
 fn version_py(py: Python) -> PyResult<String> {
     let sys = py.import("sys")?;
     let version: String = sys.get(py, "version")?.extract(py)?;
     Ok(format!("{}-{}", "0.1.0", version))
 }
 
-fn parse_py(py: Python, path: &str, name: &str, modules: Vec<String>) -> PyResult<PyTuple> {
-    use cpython::exc::Exception;
-    use cpython::ToPyObject;
+// Loads the modules and header_operations, parses the file with `parse` and raises its errors
+// and warnings.
+fn parse_file_py<T, F>(
+    py: Python,
+    path: &str,
+    arguments: &Arguments,
+    parse: F,
+) -> PyResult<PyObject>
+where
+    T: ToPyObject,
+    F: FnOnce(Python, &str, &PyDict, &parser::Options) -> Result<T, parser::Error>,
+{
+    let globals = load_globals(py, &arguments.modules)?;
+    let options = load_options(py, arguments)?;
+
+    let result = parse(py, path, &globals, &options);
+    let parsed = finish(py, result, &options)?;
+    Ok(parsed.to_py_object(py).into_object())
+}
+
+// Nothing is changed unless every file parses and every script it extends exists.
+fn apply_patches(
+    py: Python,
+    scripts: PyList,
+    paths: &[String],
+    arguments: &Arguments,
+) -> PyResult<PyObject> {
+    let globals = load_globals(py, &arguments.modules)?;
+    let options = load_options(py, arguments)?;
+
+    let result = parser::find_patches(py, &scripts, paths, &globals, &options);
+    let patches = finish(py, result, &options)?;
+    for (patch, index) in patches.iter() {
+        patch.apply(py, &scripts, *index)?;
+    }
+
+    Ok(scripts.into_object())
+}
+
+// Issues the warnings that can only be given once every file has been parsed.
+fn finish_project_py(py: Python, colour: bool, warnings_as_errors: bool) -> PyResult<PyObject> {
+    let colour = colour && std::io::stderr().is_terminal();
+    report(py, Ok(()), parser::finish_project(), colour, warnings_as_errors)?;
+    Ok(py.None())
+}
 
+fn to_py_err(py: Python, e: parser::Error, colour: bool) -> PyErr {
+    match parser::to_exception(py, &e, colour) {
+        Ok(exception) => PyErr::from_instance(py, exception),
+        Err(err) => err,
+    }
+}
+
+fn load_globals(py: Python, modules: &[String]) -> PyResult<PyDict> {
     let mut imports = vec![];
 
     for module_name in modules.iter() {
@@ -46,12 +202,101 @@ fn parse_py(py: Python, path: &str, name: &str, modules: Vec<String>) -> PyResul
         imports.push(module);
     }
 
-    let globals = build_globals_dict(py, imports)?;
+    build_globals_dict(py, imports)
+}
+
+fn load_options(py: Python, arguments: &Arguments) -> PyResult<parser::Options> {
+    use cpython::exc::ValueError;
 
-    match parser::parse(path, name, &globals) {
-        Ok(script) => Ok(script.to_py_object(py)),
-        Err(e) => Err(PyErr::new::<Exception, _>(py, e.to_string())),
+    let target = match parser::Target::from_name(arguments.target) {
+        Some(target) => target,
+        None => {
+            let msg = format!("Unknown target '{}'.", arguments.target);
+            return Err(PyErr::new::<ValueError, _>(py, msg));
+        }
+    };
+
+    let module = py.import("header_operations")?;
+    let dict = module.get(py, "__dict__")?.cast_into::<PyDict>(py)?;
+    let mut definitions = vec![];
+    for (key, val) in dict.items(py).iter() {
+        if let (Ok(name), Ok(opcode)) = (key.extract::<String>(py), val.extract::<i64>(py)) {
+            definitions.push((name, opcode));
+        }
     }
+
+    // Operand signatures come from the usage notes in the source, which is skipped if only
+    // the compiled module is around.
+    let path: String = module.get(py, "__file__")?.extract(py)?;
+    let path = path.strip_suffix('c').unwrap_or(&path);
+
+    let header = parser::Header {
+        definitions,
+        lhs_operations: load_opcodes(py, &dict, "lhs_operations")?,
+        global_lhs_operations: load_opcodes(py, &dict, "global_lhs_operations")?,
+        can_fail_operations: load_opcodes(py, &dict, "can_fail_operations")?,
+        source: std::fs::read_to_string(path).unwrap_or_default(),
+    };
+
+    // Colour is only wanted when someone's there to read it.
+    let colour = arguments.colour && std::io::stderr().is_terminal();
+
+    let overrides = match arguments.signatures {
+        Some(ref path) => match std::fs::read_to_string(path) {
+            Ok(overrides) => Some(overrides),
+            Err(_) => {
+                let error = parser::Error::FailedPathLoad(path.clone());
+                return Err(to_py_err(py, error, colour));
+            }
+        },
+        None => None,
+    };
+
+    Ok(parser::Options::new(
+        arguments.defines.clone(),
+        target,
+        header,
+        &arguments.modules,
+        overrides.as_deref(),
+        colour,
+        arguments.warnings_as_errors,
+    ))
+}
+
+// Reads one of header_operations' opcode lists, which older headers may not define.
+fn load_opcodes(py: Python, dict: &PyDict, name: &str) -> PyResult<Vec<i64>> {
+    match dict.get_item(py, name) {
+        Some(opcodes) => opcodes.extract(py),
+        None => Ok(vec![]),
+    }
+}
+
+// Warnings are issued once the result is known, unless they're to be raised with the errors.
+fn finish<T>(
+    py: Python,
+    result: Result<T, parser::Error>,
+    options: &parser::Options,
+) -> PyResult<T> {
+    let warnings = options.take_warnings();
+    report(py, result, warnings, options.colour, options.warnings_as_errors)
+}
+
+fn report<T>(
+    py: Python,
+    result: Result<T, parser::Error>,
+    warnings: Vec<parser::Warning>,
+    colour: bool,
+    warnings_as_errors: bool,
+) -> PyResult<T> {
+    if warnings_as_errors && !warnings.is_empty() {
+        let error = parser::Error::with_warnings(result.err(), warnings);
+        return Err(to_py_err(py, error, colour));
+    }
+    for warning in warnings.iter() {
+        parser::warn(py, warning, colour)?;
+    }
+
+    result.map_err(|e| to_py_err(py, e, colour))
 }
 
 fn build_globals_dict(py: Python, imports: Vec<PyModule>) -> PyResult<PyDict> {
@@ -66,3 +311,78 @@ fn build_globals_dict(py: Python, imports: Vec<PyModule>) -> PyResult<PyDict> {
 
     Ok(globals)
 }
+
+#[cfg(test)]
+mod test {
+    use cpython::{NoArgs, ObjectProtocol, PyDict, PyList, PyModule, PyObject, Python};
+
+    use super::finish;
+    use crate::parser;
+    use crate::parser::test::{options, source_file, with_python};
+
+    // Warnings look the MbsWarning class up on the module, as it would be once imported.
+    fn import_module(py: Python) {
+        let module = PyModule::new(py, "mb_ext_script").unwrap();
+        parser::add_exceptions(py, &module).unwrap();
+        let modules = py.import("sys").unwrap().get(py, "modules").unwrap();
+        modules.set_item(py, "mb_ext_script", module).unwrap();
+    }
+
+    fn attribute(py: Python, object: &PyObject, name: &str) -> String {
+        let value = object.getattr(py, name).unwrap();
+        value.str(py).unwrap().to_string(py).unwrap().into_owned()
+    }
+
+    #[test]
+    pub fn warning_test() {
+        with_python(|py, globals| {
+            import_module(py);
+            let path = source_file("warning", "assign :unused 1;\n");
+            let options = options(false);
+            let result = parser::parse(py, &path, "warning", globals, &options);
+
+            let warnings = py.import("warnings").unwrap();
+            let kwargs = PyDict::new(py);
+            kwargs.set_item(py, "record", true).unwrap();
+            let catcher = warnings.call(py, "catch_warnings", NoArgs, Some(&kwargs)).unwrap();
+            let caught = catcher.call_method(py, "__enter__", NoArgs, None).unwrap();
+            warnings.call(py, "simplefilter", ("always",), None).unwrap();
+            let finished = finish(py, result, &options);
+            let exit = (py.None(), py.None(), py.None());
+            catcher.call_method(py, "__exit__", exit, None).unwrap();
+            assert!(finished.is_ok());
+
+            let caught = caught.cast_into::<PyList>(py).unwrap();
+            assert_eq!(caught.len(py), 1);
+            let warning = caught.get_item(py, 0).getattr(py, "message").unwrap();
+            assert_eq!(warning.get_type(py).name(py), "MbsWarning");
+            assert_eq!(attribute(py, &warning, "path"), path);
+            assert_eq!(attribute(py, &warning, "line"), "1");
+            assert_eq!(attribute(py, &warning, "column"), "8");
+            assert_eq!(attribute(py, &warning, "code"), "W0001");
+            let message = "Local ':unused' is assigned but never used.";
+            assert_eq!(attribute(py, &warning, "message"), message);
+        });
+    }
+
+    #[test]
+    pub fn warnings_as_errors_test() {
+        with_python(|py, globals| {
+            let path = source_file("warnings_as_errors", "eq 1 2;\nasign :x 1;\n");
+            let options = options(true);
+            let result = parser::parse(py, &path, "warnings_as_errors", globals, &options);
+
+            // The warning about the script's name is raised along with the unknown operation.
+            let mut error = finish(py, result, &options).err().unwrap();
+            let exception = error.instance(py);
+            assert_eq!(exception.get_type(py).name(py), "MbsError");
+            let errors = exception.getattr(py, "errors").unwrap();
+            let codes: Vec<String> = errors
+                .iter(py)
+                .unwrap()
+                .map(|error| attribute(py, &error.unwrap(), "code"))
+                .collect();
+            assert_eq!(codes, vec!["W0003", "E0201"]);
+        });
+    }
+}
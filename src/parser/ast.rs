@@ -1,73 +1,421 @@
-use cpython::{PyDict, PyInt, PyList, PyObject, PyString, PyTuple, Python, ToPyObject};
+use cpython::{
+    PyClone, PyFloat, PyInt, PyList, PyObject, PyResult, PyString, PyTuple, Python,
+    PythonObject, ToPyObject,
+};
 
-pub struct Script<'a> {
+use crate::parser::codes::Code;
+use crate::parser::{Error, SourceError};
+
+pub struct Script {
     name: String,
-    statements: Vec<Statement<'a>>,
+    statements: StatementBlock,
 }
 
-impl<'a> Script<'a> {
-    pub(crate) fn new<T: ToString>(name: T) -> Self {
+impl Script {
+    pub(crate) fn new<T: ToString>(name: T, statements: StatementBlock) -> Self {
         Script {
             name: name.to_string(),
-            statements: vec![],
+            statements,
         }
     }
+}
+
+impl ToPyObject for Script {
+    type ObjectType = PyTuple;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        let statements = self.statements.to_py_object(py);
+        let name = PyString::new(py, &self.name);
 
-    pub fn push_statement(&mut self, statement: Statement<'a>) {
+        let script = vec![name.into_object(), statements.into_object()];
+        PyTuple::new(py, &script)
+    }
+}
+
+pub struct StatementBlock {
+    statements: Vec<Statement>,
+}
+
+impl StatementBlock {
+    pub(crate) fn new() -> Self {
+        StatementBlock { statements: vec![] }
+    }
+
+    pub fn push_statement(&mut self, statement: Statement) {
         self.statements.push(statement);
     }
 }
 
-impl<'a> ToPyObject for Script<'a> {
-    type ObjectType = PyTuple;
+impl ToPyObject for StatementBlock {
+    type ObjectType = PyList;
 
     fn to_py_object(&self, py: Python) -> Self::ObjectType {
-        use cpython::PythonObject;
-
         let statements: Vec<PyObject> = self
             .statements
             .iter()
             .map(|v| v.to_py_object(py).into_object())
             .collect();
 
-        let statements = PyList::new(py, &statements);
-        let name = PyString::new(py, &self.name);
+        PyList::new(py, &statements)
+    }
+}
 
-        let script = vec![name.into_object(), statements.into_object()];
-        PyTuple::new(py, &script)
+pub enum Interval {
+    Number(f64),
+    Constant(PyObject),
+}
+
+impl ToPyObject for Interval {
+    type ObjectType = PyObject;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        match self {
+            Interval::Number(num) => PyFloat::new(py, *num).into_object(),
+            Interval::Constant(ref constant) => constant.clone_ref(py),
+        }
+    }
+}
+
+// (check_interval, delay_interval, rearm_interval, conditions, consequences)
+pub struct Trigger {
+    check_interval: Interval,
+    delay_interval: Interval,
+    rearm_interval: Interval,
+    conditions: StatementBlock,
+    consequences: StatementBlock,
+}
+
+impl Trigger {
+    pub(crate) fn new(
+        check_interval: Interval,
+        delay_interval: Interval,
+        rearm_interval: Interval,
+        conditions: StatementBlock,
+        consequences: StatementBlock,
+    ) -> Self {
+        Trigger {
+            check_interval,
+            delay_interval,
+            rearm_interval,
+            conditions,
+            consequences,
+        }
+    }
+}
+
+impl ToPyObject for Trigger {
+    type ObjectType = PyTuple;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        let trigger = vec![
+            self.check_interval.to_py_object(py),
+            self.delay_interval.to_py_object(py),
+            self.rearm_interval.to_py_object(py),
+            self.conditions.to_py_object(py).into_object(),
+            self.consequences.to_py_object(py).into_object(),
+        ];
+        PyTuple::new(py, &trigger)
+    }
+}
+
+// (interval, statements)
+pub struct SimpleTrigger {
+    interval: Interval,
+    statements: StatementBlock,
+}
+
+impl SimpleTrigger {
+    pub(crate) fn new(interval: Interval, statements: StatementBlock) -> Self {
+        SimpleTrigger {
+            interval,
+            statements,
+        }
+    }
+}
+
+impl ToPyObject for SimpleTrigger {
+    type ObjectType = PyTuple;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        let trigger = vec![
+            self.interval.to_py_object(py),
+            self.statements.to_py_object(py).into_object(),
+        ];
+        PyTuple::new(py, &trigger)
+    }
+}
+
+// [partner, start_state, conditions, text, end_state, consequences]
+pub struct Dialog {
+    partner: i64,
+    start_state: String,
+    conditions: StatementBlock,
+    text: String,
+    end_state: String,
+    consequences: StatementBlock,
+}
+
+impl Dialog {
+    pub(crate) fn new(
+        partner: i64,
+        start_state: String,
+        conditions: StatementBlock,
+        text: String,
+        end_state: String,
+        consequences: StatementBlock,
+    ) -> Self {
+        Dialog {
+            partner,
+            start_state,
+            conditions,
+            text,
+            end_state,
+            consequences,
+        }
+    }
+}
+
+impl ToPyObject for Dialog {
+    type ObjectType = PyList;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        use std::os::raw::c_long;
+
+        let dialog = vec![
+            PyInt::new(py, self.partner as c_long).into_object(),
+            PyString::new(py, &self.start_state).into_object(),
+            self.conditions.to_py_object(py).into_object(),
+            PyString::new(py, &self.text).into_object(),
+            PyString::new(py, &self.end_state).into_object(),
+            self.consequences.to_py_object(py).into_object(),
+        ];
+        PyList::new(py, &dialog)
+    }
+}
+
+// (id, flags, text, mesh, on_enter, options)
+pub struct Menu {
+    id: String,
+    flags: i64,
+    text: String,
+    mesh: String,
+    on_enter: StatementBlock,
+    options: Vec<MenuOption>,
+}
+
+impl Menu {
+    pub(crate) fn new(
+        id: String,
+        flags: i64,
+        text: String,
+        mesh: String,
+        on_enter: StatementBlock,
+        options: Vec<MenuOption>,
+    ) -> Self {
+        Menu {
+            id,
+            flags,
+            text,
+            mesh,
+            on_enter,
+            options,
+        }
+    }
+}
+
+impl ToPyObject for Menu {
+    type ObjectType = PyTuple;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        use std::os::raw::c_long;
+
+        let options: Vec<PyObject> = self
+            .options
+            .iter()
+            .map(|v| v.to_py_object(py).into_object())
+            .collect();
+
+        let menu = vec![
+            PyString::new(py, &self.id).into_object(),
+            PyInt::new(py, self.flags as c_long).into_object(),
+            PyString::new(py, &self.text).into_object(),
+            PyString::new(py, &self.mesh).into_object(),
+            self.on_enter.to_py_object(py).into_object(),
+            PyList::new(py, &options).into_object(),
+        ];
+        PyTuple::new(py, &menu)
+    }
+}
+
+// (id, conditions, text, consequences)
+pub struct MenuOption {
+    id: String,
+    conditions: StatementBlock,
+    text: String,
+    consequences: StatementBlock,
+}
+
+impl MenuOption {
+    pub(crate) fn new(
+        id: String,
+        conditions: StatementBlock,
+        text: String,
+        consequences: StatementBlock,
+    ) -> Self {
+        MenuOption {
+            id,
+            conditions,
+            text,
+            consequences,
+        }
+    }
+}
+
+impl ToPyObject for MenuOption {
+    type ObjectType = PyTuple;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        let option = vec![
+            PyString::new(py, &self.id).into_object(),
+            self.conditions.to_py_object(py).into_object(),
+            PyString::new(py, &self.text).into_object(),
+            self.consequences.to_py_object(py).into_object(),
+        ];
+        PyTuple::new(py, &option)
+    }
+}
+
+// (id, flags, mesh, triggers)
+pub struct Presentation {
+    id: String,
+    flags: i64,
+    mesh: i64,
+    triggers: Vec<SimpleTrigger>,
+}
+
+impl Presentation {
+    pub(crate) fn new(id: String, flags: i64, mesh: i64, triggers: Vec<SimpleTrigger>) -> Self {
+        Presentation {
+            id,
+            flags,
+            mesh,
+            triggers,
+        }
+    }
+}
+
+impl ToPyObject for Presentation {
+    type ObjectType = PyTuple;
+
+    fn to_py_object(&self, py: Python) -> Self::ObjectType {
+        use std::os::raw::c_long;
+
+        let triggers: Vec<PyObject> = self
+            .triggers
+            .iter()
+            .map(|v| v.to_py_object(py).into_object())
+            .collect();
+
+        let presentation = vec![
+            PyString::new(py, &self.id).into_object(),
+            PyInt::new(py, self.flags as c_long).into_object(),
+            PyInt::new(py, self.mesh as c_long).into_object(),
+            PyList::new(py, &triggers).into_object(),
+        ];
+        PyTuple::new(py, &presentation)
     }
 }
 
-pub struct Statement<'a> {
-    globals: &'a PyDict,
-    operation: String,
-    parameters: Vec<StatementParameter<'a>>,
+pub enum PatchMode {
+    Prepend,
+    Append,
+    Replace,
 }
 
-impl<'a> Statement<'a> {
-    pub fn new(
-        operation: String,
-        parameters: Vec<StatementParameter<'a>>,
-        globals: &'a PyDict,
+pub struct ScriptPatch {
+    name: String,
+    mode: PatchMode,
+    statements: StatementBlock,
+    location: (usize, usize), // Line, column
+    line: String,             // As it was parsed, quoted if the script isn't found
+}
+
+impl ScriptPatch {
+    pub(crate) fn new(
+        name: String,
+        mode: PatchMode,
+        statements: StatementBlock,
+        location: (usize, usize),
+        line: String,
     ) -> Self {
+        ScriptPatch {
+            name,
+            mode,
+            statements,
+            location,
+            line,
+        }
+    }
+
+    // Finds the first `(name, statements)` tuple in `scripts` with a matching name.
+    pub fn find(&self, py: Python, scripts: &PyList) -> Option<usize> {
+        (0..scripts.len(py)).find(|&index| {
+            let script = match scripts.get_item(py, index).cast_into::<PyTuple>(py) {
+                Ok(script) => script,
+                Err(_) => return false,
+            };
+            let name = script.get_item(py, 0).extract::<String>(py).ok();
+            script.len(py) >= 2 && name.as_ref() == Some(&self.name)
+        })
+    }
+
+    // Splices the statements into the script `find` found at `index`.
+    pub fn apply(&self, py: Python, scripts: &PyList, index: usize) -> PyResult<()> {
+        let script = scripts.get_item(py, index).cast_into::<PyTuple>(py)?;
+        let existing = script.get_item(py, 1).cast_into::<PyList>(py)?;
+        let existing: Vec<PyObject> = existing.iter(py).collect();
+        let patch: Vec<PyObject> = self.statements.to_py_object(py).iter(py).collect();
+        let statements = match self.mode {
+            PatchMode::Prepend => patch.into_iter().chain(existing).collect(),
+            PatchMode::Append => existing.into_iter().chain(patch).collect(),
+            PatchMode::Replace => patch,
+        };
+
+        let mut items: Vec<PyObject> = script.iter(py).map(|v| v.clone_ref(py)).collect();
+        items[1] = PyList::new(py, &statements).into_object();
+        scripts.set_item(py, index, PyTuple::new(py, &items).into_object());
+        Ok(())
+    }
+
+    pub fn not_found(&self) -> Error {
+        let description = format!("Script '{}' to extend was not found.", self.name);
+        let code = Code::ScriptNotFound;
+        Error::SourceError(Box::new(SourceError {
+            lines: vec![(self.location.0, self.line.clone())],
+            ..SourceError::new(self.location, code, description)
+        }))
+    }
+}
+
+pub struct Statement {
+    operation: PyObject,
+    parameters: Vec<StatementParameter>,
+}
+
+impl Statement {
+    pub fn new(operation: PyObject, parameters: Vec<StatementParameter>) -> Self {
         Self {
-            globals,
             operation,
             parameters,
         }
     }
 }
 
-impl<'a> ToPyObject for Statement<'a> {
+impl ToPyObject for Statement {
     type ObjectType = PyObject;
 
     fn to_py_object(&self, py: Python) -> Self::ObjectType {
-        use cpython::PythonObject;
-
-        let op = self
-            .globals
-            .get_item(py, &self.operation)
-            .unwrap_or_else(|| py.NotImplemented());
+        let op = self.operation.clone_ref(py);
         let mut params: Vec<PyObject> =
             self.parameters.iter().map(|v| v.to_py_object(py)).collect();
 
@@ -82,9 +430,9 @@ impl<'a> ToPyObject for Statement<'a> {
     }
 }
 
-pub enum StatementParameter<'a> {
-    Identifier(String, &'a PyDict),
-    Register(u8, &'a PyDict),
+pub enum StatementParameter {
+    Identifier(PyObject),
+    Register(u8, PyObject), // With the value of reg<N> it was resolved to
     StringRegister(u8),
     PositionRegister(u8),
     LocalVariable(String),
@@ -92,24 +440,17 @@ pub enum StatementParameter<'a> {
     AutoPrefixedGlobalVariable(String),
     StatementParamId(StatementParameterId),
     Number(i64),
+    String(String),
 }
 
-fn get_register(py: Python, code: u8, globals: &PyDict) -> PyObject {
-    let key = format!("reg{}", code);
-    globals.get_item(py, &key).unwrap_or_else(|| py.NotImplemented())
-}
-
-impl<'a> ToPyObject for StatementParameter<'a> {
+impl ToPyObject for StatementParameter {
     type ObjectType = PyObject;
 
     fn to_py_object(&self, py: Python) -> Self::ObjectType {
-        use cpython::PythonObject;
         use std::os::raw::c_long;
         match self {
-            StatementParameter::Identifier(ref id, globals) => globals
-                .get_item(py, id)
-                .unwrap_or_else(|| py.NotImplemented()),
-            StatementParameter::Register(reg_code, dict) => get_register(py, *reg_code, dict),
+            StatementParameter::Identifier(ref value) => value.clone_ref(py),
+            StatementParameter::Register(_, ref value) => value.clone_ref(py),
             StatementParameter::StringRegister(reg_code) => {
                 PyInt::new(py, *reg_code as c_long).into_object()
             }
@@ -132,10 +473,12 @@ impl<'a> ToPyObject for StatementParameter<'a> {
                 param_id.to_py_object(py).into_object()
             }
             StatementParameter::Number(num) => PyInt::new(py, *num as c_long).into_object(),
+            StatementParameter::String(ref text) => PyString::new(py, text).into_object(),
         }
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 pub enum StatementParameterId {
     AnimationId(String),
     FactionId(String),
@@ -161,36 +504,69 @@ pub enum StatementParameterId {
     TroopId(String),
 }
 
+impl StatementParameterId {
+    pub fn name(&self) -> String {
+        match self {
+            StatementParameterId::AnimationId(ref id) => format!("anim_{}", id),
+            StatementParameterId::FactionId(ref id) => format!("fac_{}", id),
+            StatementParameterId::InfoPageId(ref id) => format!("id_{}", id),
+            StatementParameterId::ItemId(ref id) => format!("itm_{}", id),
+            StatementParameterId::MapIconId(ref id) => format!("icon_{}", id),
+            StatementParameterId::GameMenuId(ref id) => format!("mnu_{}", id),
+            StatementParameterId::MeshId(ref id) => format!("mesh_{}", id),
+            StatementParameterId::MissionTemplateId(ref id) => format!("mst_{}", id),
+            StatementParameterId::ParticleSystemId(ref id) => format!("psys_{}", id),
+            StatementParameterId::PartyId(ref id) => format!("p_{}", id),
+            StatementParameterId::PartyTemplateId(ref id) => format!("pt_{}", id),
+            StatementParameterId::PostfxId(ref id) => format!("pfx_{}", id),
+            StatementParameterId::PresentationId(ref id) => format!("prsnt_{}", id),
+            StatementParameterId::QuestId(ref id) => format!("qst_{}", id),
+            StatementParameterId::ScenePropId(ref id) => format!("spr_{}", id),
+            StatementParameterId::SceneId(ref id) => format!("scn_{}", id),
+            StatementParameterId::ScriptId(ref id) => format!("script_{}", id),
+            StatementParameterId::SkillId(ref id) => format!("skl_{}", id),
+            StatementParameterId::SoundId(ref id) => format!("snd_{}", id),
+            StatementParameterId::StringId(ref id) => format!("str_{}", id),
+            StatementParameterId::TableauMaterialId(ref id) => format!("tableau_{}", id),
+            StatementParameterId::TroopId(ref id) => format!("trp_{}", id),
+        }
+    }
+}
+
+impl StatementParameterId {
+    // The module the module system writes this kind of id out to.
+    pub fn module(&self) -> &'static str {
+        match self {
+            StatementParameterId::AnimationId(_) => "ID_animations",
+            StatementParameterId::FactionId(_) => "ID_factions",
+            StatementParameterId::InfoPageId(_) => "ID_info_pages",
+            StatementParameterId::ItemId(_) => "ID_items",
+            StatementParameterId::MapIconId(_) => "ID_map_icons",
+            StatementParameterId::GameMenuId(_) => "ID_menus",
+            StatementParameterId::MeshId(_) => "ID_meshes",
+            StatementParameterId::MissionTemplateId(_) => "ID_mission_templates",
+            StatementParameterId::ParticleSystemId(_) => "ID_particle_systems",
+            StatementParameterId::PartyId(_) => "ID_parties",
+            StatementParameterId::PartyTemplateId(_) => "ID_party_templates",
+            StatementParameterId::PostfxId(_) => "ID_postfx_params",
+            StatementParameterId::PresentationId(_) => "ID_presentations",
+            StatementParameterId::QuestId(_) => "ID_quests",
+            StatementParameterId::ScenePropId(_) => "ID_scene_props",
+            StatementParameterId::SceneId(_) => "ID_scenes",
+            StatementParameterId::ScriptId(_) => "ID_scripts",
+            StatementParameterId::SkillId(_) => "ID_skills",
+            StatementParameterId::SoundId(_) => "ID_sounds",
+            StatementParameterId::StringId(_) => "ID_strings",
+            StatementParameterId::TableauMaterialId(_) => "ID_tableau_materials",
+            StatementParameterId::TroopId(_) => "ID_troops",
+        }
+    }
+}
+
 impl ToPyObject for StatementParameterId {
     type ObjectType = PyString;
 
     fn to_py_object(&self, py: Python) -> Self::ObjectType {
-        PyString::new(
-            py,
-            &match self {
-                StatementParameterId::AnimationId(ref id) => format!("anim_{}", id),
-                StatementParameterId::FactionId(ref id) => format!("fac_{}", id),
-                StatementParameterId::InfoPageId(ref id) => format!("id_{}", id),
-                StatementParameterId::ItemId(ref id) => format!("itm_{}", id),
-                StatementParameterId::MapIconId(ref id) => format!("icon_{}", id),
-                StatementParameterId::GameMenuId(ref id) => format!("menu_{}", id),
-                StatementParameterId::MeshId(ref id) => format!("mesh_{}", id),
-                StatementParameterId::MissionTemplateId(ref id) => format!("mst_{}", id),
-                StatementParameterId::ParticleSystemId(ref id) => format!("psys_{}", id),
-                StatementParameterId::PartyId(ref id) => format!("p_{}", id),
-                StatementParameterId::PartyTemplateId(ref id) => format!("pt_{}", id),
-                StatementParameterId::PostfxId(ref id) => format!("pfx_{}", id),
-                StatementParameterId::PresentationId(ref id) => format!("prsnt_{}", id),
-                StatementParameterId::QuestId(ref id) => format!("qst_{}", id),
-                StatementParameterId::ScenePropId(ref id) => format!("spr_{}", id),
-                StatementParameterId::SceneId(ref id) => format!("scn_{}", id),
-                StatementParameterId::ScriptId(ref id) => format!("script_{}", id),
-                StatementParameterId::SkillId(ref id) => format!("skl_{}", id),
-                StatementParameterId::SoundId(ref id) => format!("snd_{}", id),
-                StatementParameterId::StringId(ref id) => format!("str_{}", id),
-                StatementParameterId::TableauMaterialId(ref id) => format!("tableau_{}", id),
-                StatementParameterId::TroopId(ref id) => format!("trp_{}", id),
-            },
-        )
+        PyString::new(py, &self.name())
     }
 }
@@ -0,0 +1,87 @@
+// Which exception an error is raised as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Category {
+    SourceLoad, // The file couldn't be read
+    Parse,      // It isn't valid mbs
+    Resolve,    // A name in it doesn't refer to anything
+    Validation, // It's valid and resolves, but wouldn't work in the game
+}
+
+// Identifies each kind of error and warning, so scripts building a module can tell them apart
+// without matching on the message.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Code {
+    SourceLoad,
+    Syntax,
+    Directive,
+    UnknownOperation,
+    UnknownName,
+    UnknownId,
+    UnknownType,
+    OutOfScope,
+    ScriptNotFound,
+    InvalidNumber,
+    Unavailable,
+    OperandCount,
+    OperandKind,
+    TypeMismatch,
+    Redeclared,
+    RegisterRange,
+    RegisterAliased,
+    TryBlock,
+    UnusedVariable,
+    UnassignedVariable,
+    FailureNaming,
+    EngineRegister,
+    Shadowed,
+    Deprecated,
+    RegisterClash,
+    UncheckedMenu,
+}
+
+impl Code {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::SourceLoad => "E0001",
+            Code::Syntax => "E0101",
+            Code::Directive => "E0102",
+            Code::UnknownOperation => "E0201",
+            Code::UnknownName => "E0202",
+            Code::UnknownId => "E0203",
+            Code::UnknownType => "E0204",
+            Code::OutOfScope => "E0205",
+            Code::ScriptNotFound => "E0206",
+            Code::InvalidNumber => "E0301",
+            Code::Unavailable => "E0302",
+            Code::OperandCount => "E0303",
+            Code::OperandKind => "E0304",
+            Code::TypeMismatch => "E0305",
+            Code::Redeclared => "E0306",
+            Code::RegisterRange => "E0307",
+            Code::RegisterAliased => "E0308",
+            Code::TryBlock => "E0309",
+            Code::UnusedVariable => "W0001",
+            Code::UnassignedVariable => "W0002",
+            Code::FailureNaming => "W0003",
+            Code::EngineRegister => "W0004",
+            Code::Shadowed => "W0005",
+            Code::Deprecated => "W0006",
+            Code::RegisterClash => "W0007",
+            Code::UncheckedMenu => "W0008",
+        }
+    }
+
+    pub fn category(self) -> Category {
+        match self {
+            Code::SourceLoad => Category::SourceLoad,
+            Code::Syntax | Code::Directive => Category::Parse,
+            Code::UnknownOperation
+            | Code::UnknownName
+            | Code::UnknownId
+            | Code::UnknownType
+            | Code::OutOfScope
+            | Code::ScriptNotFound => Category::Resolve,
+            _ => Category::Validation,
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use pest::iterators::Pair;
+
+use crate::parser::ast;
+use crate::parser::codes::Code;
+use crate::parser::parser_inner::Rule;
+use crate::parser::{parse_id, parse_items, quote_line, source_error, Context, Error};
+
+pub(super) fn parse_trigger<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Trigger, Error> {
+    context.in_trigger = true;
+    let mut inner = pair.into_inner();
+    let check_interval = parse_interval(inner.next().expect("Failed to get interval."), context)?;
+    let mut delay_interval = ast::Interval::Number(0.0);
+    let mut rearm_interval = ast::Interval::Number(0.0);
+    let mut conditions = ast::StatementBlock::new();
+    let mut consequences = ast::StatementBlock::new();
+
+    for part in inner {
+        match part.as_rule() {
+            Rule::trigger_delay => delay_interval = parse_inner_interval(part, context)?,
+            Rule::trigger_rearm => rearm_interval = parse_inner_interval(part, context)?,
+            Rule::condition_block => conditions = parse_inner_block(part, context)?,
+            Rule::consequence_block => consequences = parse_inner_block(part, context)?,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(ast::Trigger::new(
+        check_interval,
+        delay_interval,
+        rearm_interval,
+        conditions,
+        consequences,
+    ))
+}
+
+pub(super) fn parse_simple_trigger<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::SimpleTrigger, Error> {
+    context.in_trigger = true;
+    let mut inner = pair.into_inner();
+    let interval = parse_interval(inner.next().expect("Failed to get interval."), context)?;
+    let statements = parse_block(inner.next().expect("Failed to get block."), context)?;
+
+    Ok(ast::SimpleTrigger::new(interval, statements))
+}
+
+pub(super) fn parse_dialog<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Dialog, Error> {
+    let mut inner = pair.into_inner();
+    let partner = parse_bit_flags(inner.next().expect("Failed to get partner."), context)?;
+    let start_state = parse_string(inner.next().expect("Failed to get start state."));
+    let end_state = parse_string(inner.next().expect("Failed to get end state."));
+    let text = parse_string(inner.next().expect("Failed to get dialog text."));
+    let mut conditions = ast::StatementBlock::new();
+    let mut consequences = ast::StatementBlock::new();
+
+    for part in inner {
+        match part.as_rule() {
+            Rule::condition_block => conditions = parse_inner_block(part, context)?,
+            Rule::consequence_block => consequences = parse_inner_block(part, context)?,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(ast::Dialog::new(
+        partner,
+        start_state,
+        conditions,
+        text,
+        end_state,
+        consequences,
+    ))
+}
+
+pub(super) fn parse_menu<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Menu, Error> {
+    let mut inner = pair.into_inner();
+    let id = inner.next().expect("Failed to get menu id.").as_str().to_string();
+    let mut flags = 0;
+    let mut text = None;
+    let mut mesh = None;
+    let mut on_enter = ast::StatementBlock::new();
+    let mut options = vec![];
+
+    for part in inner {
+        match part.as_rule() {
+            Rule::menu_flags => {
+                let bit_flags = part.into_inner().next().expect("Failed to get menu flags.");
+                flags = parse_bit_flags(bit_flags, context)?;
+            }
+            Rule::statement_parameter_string if text.is_none() => text = Some(parse_string(part)),
+            Rule::statement_parameter_string => mesh = Some(parse_string(part)),
+            Rule::on_enter_block => on_enter = parse_inner_block(part, context)?,
+            Rule::menu_option => options.push(parse_menu_option(part, context)?),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(ast::Menu::new(
+        id,
+        flags,
+        text.expect("Failed to get menu text."),
+        mesh.expect("Failed to get menu mesh."),
+        on_enter,
+        options,
+    ))
+}
+
+pub(super) fn parse_presentation<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Presentation, Error> {
+    let mut inner = pair.into_inner();
+    let id = inner.next().expect("Failed to get presentation id.").as_str().to_string();
+    let mut flags = 0;
+    let mut mesh = 0;
+    let mut triggers = vec![];
+
+    for part in inner {
+        match part.as_rule() {
+            Rule::presentation_flags => {
+                let bit_flags = part.into_inner().next().expect("Failed to get flags.");
+                flags = parse_bit_flags(bit_flags, context)?;
+            }
+            Rule::presentation_mesh => {
+                let bit_flags = part.into_inner().next().expect("Failed to get mesh.");
+                mesh = parse_bit_flags(bit_flags, context)?;
+            }
+            // Handlers are laid out exactly like simple triggers, (event, statements).
+            Rule::presentation_handler => triggers.push(parse_simple_trigger(part, context)?),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(ast::Presentation::new(id, flags, mesh, triggers))
+}
+
+pub(super) fn parse_script_patch<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::ScriptPatch, Error> {
+    let location = pair.as_span().start_pos().line_col();
+    let mut inner = pair.into_inner();
+    let name = inner.next().expect("Failed to get script name.").as_str().to_string();
+    let mode = match inner.next().expect("Failed to get patch mode.").as_str() {
+        "prepend" => ast::PatchMode::Prepend,
+        "append" => ast::PatchMode::Append,
+        "replace" => ast::PatchMode::Replace,
+        _ => unreachable!(),
+    };
+    context.script = Some(name.clone());
+    let statements = parse_block(inner.next().expect("Failed to get block."), context);
+    context.script = None;
+
+    let line = quote_line(&context.text, location.0).unwrap_or_default();
+    Ok(ast::ScriptPatch::new(name, mode, statements?, location, line))
+}
+
+fn parse_menu_option<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::MenuOption, Error> {
+    let mut inner = pair.into_inner();
+    let id = inner.next().expect("Failed to get option id.").as_str().to_string();
+    let text = parse_string(inner.next().expect("Failed to get option text."));
+    let mut conditions = ast::StatementBlock::new();
+    let mut consequences = ast::StatementBlock::new();
+
+    for part in inner {
+        match part.as_rule() {
+            Rule::condition_block => conditions = parse_inner_block(part, context)?,
+            Rule::consequence_block => consequences = parse_inner_block(part, context)?,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(ast::MenuOption::new(id, conditions, text, consequences))
+}
+
+// Flags such as `anyone|plyr` or `trp.ramun_the_slave_trader|plyr` are or'd together here
+// since the constants and ids have to be looked up as numbers.
+fn parse_bit_flags(pair: Pair<Rule>, context: &Context) -> Result<i64, Error> {
+    let mut flags = 0;
+
+    for term in pair.into_inner() {
+        let value = term.into_inner().next().expect("Failed to get flag.");
+        let span = value.as_span();
+        let name = match value.as_rule() {
+            Rule::statement_parameter_number => {
+                flags |= value
+                    .as_str()
+                    .parse::<i64>()
+                    .map_err(|_| source_error(&span, Code::InvalidNumber, "Invalid flag number."))?;
+                continue;
+            }
+            Rule::statement_parameter_id => {
+                parse_id(value.into_inner().next().expect("Failed to get id.")).name()
+            }
+            Rule::identifier => value.as_str().to_string(),
+            _ => unreachable!(),
+        };
+
+        let constant = context.lookup(&name).ok_or_else(|| {
+            let description = format!("Unknown constant '{}'.", name);
+            context.unknown_name(&span, Code::UnknownName, &description, &name, "")
+        })?;
+        flags |= constant.extract::<i64>(context.py).map_err(|_| {
+            let description = format!("Constant '{}' is not a number.", name);
+            source_error(&span, Code::InvalidNumber, &description)
+        })?;
+    }
+
+    Ok(flags)
+}
+
+fn parse_string(pair: Pair<Rule>) -> String {
+    pair.into_inner()
+        .next()
+        .expect("Failed to get string text.")
+        .as_str()
+        .to_string()
+}
+
+fn parse_interval<'a>(pair: Pair<Rule>, context: &Context<'a>) -> Result<ast::Interval, Error> {
+    let value = pair.into_inner().next().expect("Failed to get interval value.");
+    let span = value.as_span();
+
+    match value.as_rule() {
+        Rule::interval_number => {
+            let num = value
+                .as_str()
+                .parse::<f64>()
+                .map_err(|_| source_error(&span, Code::InvalidNumber, "Invalid interval."))?;
+            Ok(ast::Interval::Number(num))
+        }
+        Rule::identifier => {
+            let name = value.as_str();
+            let constant = context.lookup(name).ok_or_else(|| {
+                let description = format!("Unknown trigger constant '{}'.", name);
+                context.unknown_name(&span, Code::UnknownName, &description, name, "")
+            })?;
+            Ok(ast::Interval::Constant(constant))
+        }
+        _ => unreachable!(),
+    }
+}
+
+// Parses the interval wrapped by a keyword rule such as `delay 0`.
+fn parse_inner_interval<'a>(
+    pair: Pair<Rule>,
+    context: &Context<'a>,
+) -> Result<ast::Interval, Error> {
+    let interval = pair.into_inner().next().expect("Failed to get interval.");
+    parse_interval(interval, context)
+}
+
+// Parses the block wrapped by a keyword rule such as `condition { ... }`.
+fn parse_inner_block<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::StatementBlock, Error> {
+    let block = pair.into_inner().next().expect("Failed to get block.");
+    parse_block(block, context)
+}
+
+fn parse_block<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::StatementBlock, Error> {
+    let mut statements = ast::StatementBlock::new();
+    context.begin_block();
+    parse_items(pair.into_inner(), &mut statements, context);
+    context.check_try_blocks_closed()?;
+    Ok(statements)
+}
@@ -0,0 +1,215 @@
+use crate::parser::SourceError;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+#[derive(Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn colour(self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+        }
+    }
+}
+
+struct Annotation<'e> {
+    location: (usize, usize),
+    end: Option<(usize, usize)>,
+    marker: char,
+    message: Option<&'e str>,
+}
+
+// Lays the error out the way rustc does, quoting each line it points at with the offending text
+// underlined. The snippet is left out when no lines were quoted, and the line when the error is
+// about the whole file.
+pub fn render(error: &SourceError, severity: Severity, colour: bool) -> String {
+    let paint = |style: &str, text: &str| {
+        if colour {
+            format!("{}{}{}", style, text, RESET)
+        }
+        else {
+            text.to_string()
+        }
+    };
+
+    let mut annotations: Vec<Annotation> = error
+        .location
+        .map(|location| Annotation {
+            location,
+            end: error.end,
+            marker: '^',
+            message: None,
+        })
+        .into_iter()
+        .collect();
+    annotations.extend(error.labels.iter().map(|(location, message)| Annotation {
+        location: *location,
+        end: None,
+        marker: '-',
+        message: Some(message),
+    }));
+
+    let quoted = |number: usize| {
+        error.lines.iter().find(|&&(line, _)| line == number).map(|(_, text)| text.as_str())
+    };
+    let mut shown: Vec<usize> = annotations
+        .iter()
+        .map(|annotation| annotation.location.0)
+        .filter(|&line| quoted(line).is_some())
+        .collect();
+    shown.sort_unstable();
+    shown.dedup();
+
+    let line = error.location.map_or(1, |(line, _)| line);
+    let gutter = " ".repeat(shown.iter().max().unwrap_or(&line).to_string().len());
+    let bar = paint(BLUE, "|");
+
+    let path = error.path.as_deref().unwrap_or("<unknown>");
+    let position = match error.location {
+        Some((line, column)) => format!("{}:{}:{}", path, line, column),
+        None => path.to_string(),
+    };
+    let mut output = format!(
+        "{}{}\n{}{} {}\n",
+        paint(severity.colour(), severity.name()),
+        paint(BOLD, &format!(": {}", error.description)),
+        gutter,
+        paint(BLUE, "-->"),
+        position
+    );
+
+    if !shown.is_empty() {
+        output.push_str(&format!("{} {}\n", gutter, bar));
+    }
+    let mut previous: Option<usize> = None;
+    for &number in shown.iter() {
+        if previous.is_some_and(|previous| number > previous + 1) {
+            output.push_str(&format!("{}\n", paint(BLUE, "...")));
+        }
+        previous = Some(number);
+
+        let text = quoted(number).unwrap_or_default().replace('\t', " ");
+        let number_text = format!("{:>width$}", number, width = gutter.len());
+        output.push_str(&format!("{} {} {}\n", paint(BLUE, &number_text), bar, text));
+
+        for annotation in annotations.iter().filter(|a| a.location.0 == number) {
+            let column = annotation.location.1;
+            let width = underline_width(&text, column, annotation.end, number);
+            let style = match annotation.marker {
+                '^' => severity.colour(),
+                _ => BLUE,
+            };
+            let mut underline = annotation.marker.to_string().repeat(width);
+            if let Some(message) = annotation.message {
+                underline = format!("{} {}", underline, message);
+            }
+            output.push_str(&format!(
+                "{} {} {}{}\n",
+                gutter,
+                bar,
+                " ".repeat(column.saturating_sub(1)),
+                paint(style, &underline)
+            ));
+        }
+    }
+
+    if !error.notes.is_empty() {
+        output.push_str(&format!("{} {}\n", gutter, bar));
+    }
+    for (kind, note) in error.notes.iter() {
+        output.push_str(&format!(
+            "{} {} {} {}\n",
+            gutter,
+            paint(BLUE, "="),
+            paint(BOLD, &format!("{}:", kind)),
+            note
+        ));
+    }
+
+    output.truncate(output.trim_end().len());
+    output
+}
+
+// Without a known end the word starting at the column is underlined, stopping before any `;`.
+fn underline_width(
+    text: &str,
+    column: usize,
+    end: Option<(usize, usize)>,
+    line: usize,
+) -> usize {
+    let rest = text.chars().skip(column.saturating_sub(1));
+    let width = match end {
+        Some((end_line, end_column)) if end_line == line => end_column.saturating_sub(column),
+        Some(_) => rest.count(),
+        None => rest.take_while(|c| !c.is_whitespace() && *c != ';').count(),
+    };
+    width.max(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Severity};
+    use crate::parser::codes::Code;
+    use crate::parser::{error_at, Error, SourceError};
+
+    const SRC: &str = "let :x: troop = trp.player;\nasign :x 2;\n";
+
+    #[test]
+    pub fn render_test() {
+        let error = error_at((2, 1), Code::UnknownOperation, "Unknown operation 'asign'.")
+            .with_label((1, 5), "declared here".to_string())
+            .with_note("help", "Did you mean 'assign'?".to_string());
+        let mut error = match error {
+            Error::SourceError(error) => error,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            render(&error, Severity::Warning, false),
+            "warning: Unknown operation 'asign'.\n --> <unknown>:2:1\n  |\n  \
+             = help: Did you mean 'assign'?"
+        );
+
+        error.quote(SRC);
+        assert_eq!(
+            render(&error, Severity::Error, false),
+            "error: Unknown operation 'asign'.\n \
+             --> <unknown>:2:1\n  \
+             |\n\
+             1 | let :x: troop = trp.player;\n  \
+             |     --- declared here\n\
+             2 | asign :x 2;\n  \
+             | ^^^^^\n  \
+             |\n  \
+             = help: Did you mean 'assign'?"
+        );
+    }
+
+    #[test]
+    pub fn whole_file_test() {
+        let description = "'cf_x' can never fail but is named as if it can.".to_string();
+        let mut error = SourceError {
+            path: Some("cf_x.mbs".to_string()),
+            ..SourceError::whole_file(Code::FailureNaming, description)
+        };
+        error.quote(SRC);
+
+        assert_eq!(
+            render(&error, Severity::Warning, false),
+            "warning: 'cf_x' can never fail but is named as if it can.\n --> cf_x.mbs"
+        );
+    }
+}
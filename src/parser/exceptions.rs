@@ -0,0 +1,170 @@
+// py_exception! still expands to try!.
+#![allow(deprecated)]
+
+use cpython::{ObjectProtocol, PyClone, PyErr, PyModule, PyObject, PyResult, Python};
+
+use crate::parser::codes::{Category, Code};
+use crate::parser::{Error, SourceError, Warning};
+
+py_exception!(mb_ext_script, MbsError);
+py_exception!(mb_ext_script, ParseError, MbsError);
+py_exception!(mb_ext_script, ResolveError, MbsError);
+py_exception!(mb_ext_script, SourceLoadError, MbsError);
+py_exception!(mb_ext_script, ValidationError, MbsError);
+
+pub fn add_exceptions(py: Python, module: &PyModule) -> PyResult<()> {
+    module.add(py, "MbsError", py.get_type::<MbsError>())?;
+    module.add(py, "ParseError", py.get_type::<ParseError>())?;
+    module.add(py, "ResolveError", py.get_type::<ResolveError>())?;
+    module.add(py, "SourceLoadError", py.get_type::<SourceLoadError>())?;
+    module.add(py, "ValidationError", py.get_type::<ValidationError>())?;
+
+    // cpython has no UserWarning type for py_exception! to derive from.
+    let user_warning = py.eval("UserWarning", None, None)?;
+    let warning = PyErr::new_type(py, "mb_ext_script.MbsWarning", Some(user_warning), None);
+    module.add(py, "MbsWarning", warning)?;
+    Ok(())
+}
+
+// Raised as the subclass for what went wrong, or as MbsError itself when a file has errors of
+// more than one category. The attributes describe the first error, and `errors` holds an
+// exception for each of them.
+pub fn to_exception(py: Python, error: &Error, colour: bool) -> PyResult<PyObject> {
+    let errors = error.errors();
+    let first = category(&errors[0]);
+    let class = match first {
+        _ if errors.iter().any(|error| category(error) != first) => py.get_type::<MbsError>(),
+        Category::SourceLoad => py.get_type::<SourceLoadError>(),
+        Category::Parse => py.get_type::<ParseError>(),
+        Category::Resolve => py.get_type::<ResolveError>(),
+        Category::Validation => py.get_type::<ValidationError>(),
+    };
+
+    let exception = class.call(py, (error.render(colour),), None)?;
+    set_attributes(py, &exception, &errors[0])?;
+
+    let exceptions = match error {
+        Error::Multiple(ref errors) => errors
+            .iter()
+            .map(|error| to_exception(py, error, colour))
+            .collect::<PyResult<Vec<PyObject>>>()?,
+        _ => vec![exception.clone_ref(py)],
+    };
+    exception.setattr(py, "errors", exceptions)?;
+
+    Ok(exception)
+}
+
+// Issued through Python's warnings module, so they can be filtered or turned into exceptions
+// like any other warning.
+pub fn warn(py: Python, warning: &Warning, colour: bool) -> PyResult<()> {
+    let class = py.import("mb_ext_script")?.get(py, "MbsWarning")?;
+    let instance = class.call(py, (warning.render(colour),), None)?;
+    set_source_attributes(py, &instance, &warning.0)?;
+
+    py.import("warnings")?.call(py, "warn", (instance,), None)?;
+    Ok(())
+}
+
+fn category(error: &Error) -> Category {
+    match error {
+        Error::FailedPathLoad(_) => Category::SourceLoad,
+        Error::SourceError(ref source_error) => source_error.code.category(),
+        Error::Multiple(ref errors) => category(&errors[0]),
+    }
+}
+
+fn set_attributes(py: Python, exception: &PyObject, error: &Error) -> PyResult<()> {
+    match error {
+        Error::FailedPathLoad(ref path) => {
+            exception.setattr(py, "path", path.as_str())?;
+            for name in ["line", "column", "end_line", "end_column"] {
+                exception.setattr(py, name, py.None())?;
+            }
+            exception.setattr(py, "code", Code::SourceLoad.as_str())?;
+            exception.setattr(py, "message", "Failed to load source.")
+        }
+        Error::SourceError(ref source_error) => set_source_attributes(py, exception, source_error),
+        Error::Multiple(ref errors) => set_attributes(py, exception, &errors[0]),
+    }
+}
+
+fn set_source_attributes(py: Python, object: &PyObject, error: &SourceError) -> PyResult<()> {
+    object.setattr(py, "path", error.path.clone())?;
+    object.setattr(py, "line", error.location.map(|(line, _)| line))?;
+    object.setattr(py, "column", error.location.map(|(_, column)| column))?;
+    object.setattr(py, "end_line", error.end.map(|(line, _)| line))?;
+    object.setattr(py, "end_column", error.end.map(|(_, column)| column))?;
+    object.setattr(py, "code", error.code.as_str())?;
+    object.setattr(py, "message", error.description.as_str())
+}
+
+#[cfg(test)]
+mod test {
+    use cpython::{FromPyObject, ObjectProtocol, PyObject, Python};
+
+    use super::to_exception;
+    use crate::parser::codes::Code;
+    use crate::parser::{error_at, Error, SourceError};
+
+    fn class_name(py: Python, exception: &PyObject) -> String {
+        exception.get_type(py).name(py).into_owned()
+    }
+
+    fn attribute<T>(py: Python, exception: &PyObject, name: &str) -> T
+    where
+        for<'s> T: FromPyObject<'s>,
+    {
+        exception.getattr(py, name).unwrap().extract(py).unwrap()
+    }
+
+    #[test]
+    pub fn exception_class_test() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let resolve = || error_at((2, 1), Code::UnknownOperation, "Unknown operation 'asign'.");
+        let validation = || error_at((3, 8), Code::OperandKind, "Expected a position register.");
+        let cases = vec![
+            (Error::FailedPathLoad("missing.mbs".to_string()), "SourceLoadError"),
+            (error_at((1, 1), Code::Syntax, "Syntax error."), "ParseError"),
+            (resolve(), "ResolveError"),
+            (validation(), "ValidationError"),
+            (Error::from_errors(vec![resolve(), resolve()]), "ResolveError"),
+            (Error::from_errors(vec![resolve(), validation()]), "MbsError"),
+        ];
+        for (error, expected) in cases {
+            let exception = to_exception(py, &error, false).unwrap();
+            assert_eq!(class_name(py, &exception), expected);
+        }
+    }
+
+    #[test]
+    pub fn exception_attributes_test() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let first = Error::SourceError(Box::new(SourceError {
+            end: Some((2, 6)),
+            ..SourceError::new((2, 1), Code::UnknownOperation, "Unknown operation 'asign'.".into())
+        }));
+        let second = error_at((3, 8), Code::OperandKind, "Expected a position register.");
+        let error = Error::from_errors(vec![second, first]).in_file("script.mbs");
+        let exception = to_exception(py, &error, false).unwrap();
+
+        // The attributes describe the first error in the file.
+        assert_eq!(attribute::<String>(py, &exception, "path"), "script.mbs");
+        assert_eq!(attribute::<Option<usize>>(py, &exception, "line"), Some(2));
+        assert_eq!(attribute::<Option<usize>>(py, &exception, "column"), Some(1));
+        assert_eq!(attribute::<Option<usize>>(py, &exception, "end_line"), Some(2));
+        assert_eq!(attribute::<Option<usize>>(py, &exception, "end_column"), Some(6));
+        assert_eq!(attribute::<String>(py, &exception, "code"), "E0201");
+        assert_eq!(attribute::<String>(py, &exception, "message"), "Unknown operation 'asign'.");
+
+        let errors: Vec<PyObject> = attribute(py, &exception, "errors");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(class_name(py, &errors[1]), "ValidationError");
+        assert_eq!(attribute::<Option<usize>>(py, &errors[1], "line"), Some(3));
+        assert_eq!(attribute::<Option<usize>>(py, &errors[1], "end_line"), None);
+    }
+}
@@ -1,398 +1,2020 @@
 mod ast;
+mod codes;
+mod definitions;
+mod diagnostics;
+mod exceptions;
 mod parser_inner;
-
-pub use ast::*;
+mod preprocessor;
+mod project;
+mod recovery;
+mod registers;
+mod scope;
+mod signatures;
+mod suggestions;
+mod targets;
+mod tracking;
+mod types;
 
 use cpython::PyDict;
-use cpython::PyString;
+use cpython::PyList;
+use cpython::PyObject;
 use cpython::Python;
 use cpython::ToPyObject;
+use std::cell::RefCell;
+use std::fmt;
+
+use pest::iterators::{Pair, Pairs};
+use pest::Span;
 
+use crate::parser::codes::Code;
+use crate::parser::diagnostics::Severity;
 use crate::parser::parser_inner::{ParserInner, Rule};
+use crate::parser::registers::{Allocator, Register, RegisterKind, ENGINE_REGISTERS};
+use crate::parser::scope::{ScopeError, Scopes};
+use crate::parser::signatures::Signatures;
+use crate::parser::targets::Profile;
+use crate::parser::tracking::{
+    misnamed_failure, misnamed_success, Call, Failure, Locals, TryBlocks,
+};
+use crate::parser::types::Type;
+
+use std::collections::{HashMap, HashSet};
 
 pub struct SourceError {
-    location: (usize, usize), // Line, column
+    path: Option<String>,
+    location: Option<(usize, usize)>,      // Line, column, or None when it's about the whole file
+    end: Option<(usize, usize)>,           // Just past the offending text, when it's known
+    code: Code,
     description: String,
+    labels: Vec<((usize, usize), String)>, // Related places in the same file
+    notes: Vec<(&'static str, String)>,    // e.g. ("help", "Did you mean 'assign'?")
+    lines: Vec<(usize, String)>,           // Those quoted in the snippet, by line number
 }
 
-impl ToString for SourceError {
-    fn to_string(&self) -> String {
-        let (line, col) = self.location;
-        format!(
-            "Error '{}' at Line: {}, Col: {}",
-            self.description, line, col
-        )
+impl SourceError {
+    fn new(location: (usize, usize), code: Code, description: String) -> Self {
+        SourceError {
+            location: Some(location),
+            ..SourceError::whole_file(code, description)
+        }
+    }
+
+    fn whole_file(code: Code, description: String) -> Self {
+        SourceError {
+            path: None,
+            location: None,
+            end: None,
+            code,
+            description,
+            labels: vec![],
+            notes: vec![],
+            lines: vec![],
+        }
+    }
+
+    // Keeps the lines the error points at from the source as it was parsed, so the snippet
+    // still matches if the file changes before the error is shown.
+    fn quote(&mut self, source: &str) {
+        let labelled = self.labels.iter().map(|&(location, _)| location);
+        let mut numbers: Vec<usize> =
+            self.location.into_iter().chain(labelled).map(|(line, _)| line).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        self.lines = numbers
+            .into_iter()
+            .filter_map(|number| Some((number, quote_line(source, number)?)))
+            .collect();
+    }
+
+    fn render(&self, severity: Severity, colour: bool) -> String {
+        diagnostics::render(self, severity, colour)
     }
 }
 
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(Severity::Error, false))
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    PestError(String),
-    FailedPathLoad,
-    SourceError(SourceError),
+    FailedPathLoad(String), // The path that couldn't be read
+    SourceError(Box<SourceError>),
+    Multiple(Vec<Error>), // Everything wrong with a file, in the order it appears
+}
+
+impl Error {
+    // Errors from several files are raised together, so any that are already grouped are spread
+    // out among the rest.
+    pub fn from_errors(errors: Vec<Error>) -> Self {
+        let mut errors: Vec<Error> = errors
+            .into_iter()
+            .flat_map(|error| match error {
+                Error::Multiple(errors) => errors,
+                error => vec![error],
+            })
+            .collect();
+        errors.sort_by_key(|error| match error {
+            Error::SourceError(ref source_error) => {
+                (source_error.path.clone(), source_error.location)
+            }
+            _ => (None, None),
+        });
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => Error::Multiple(errors),
+        }
+    }
+
+    // Warnings treated as errors are raised along with any errors there already were.
+    pub fn with_warnings(error: Option<Error>, warnings: Vec<Warning>) -> Self {
+        let mut errors = match error {
+            Some(Error::Multiple(errors)) => errors,
+            Some(error) => vec![error],
+            None => vec![],
+        };
+        errors.extend(
+            warnings
+                .into_iter()
+                .map(|Warning(source_error)| Error::SourceError(Box::new(source_error))),
+        );
+        Error::from_errors(errors)
+    }
+
+    // Errors are raised without knowing the file, it's filled in on the way out of the parser.
+    pub fn in_file(self, path: &str) -> Self {
+        match self {
+            Error::SourceError(source_error) => Error::SourceError(Box::new(SourceError {
+                path: Some(path.to_string()),
+                ..*source_error
+            })),
+            Error::Multiple(errors) => {
+                Error::Multiple(errors.into_iter().map(|e| e.in_file(path)).collect())
+            }
+            error => error,
+        }
+    }
+
+    // Quotes the lines each error points at, see SourceError::quote.
+    fn quoting(self, source: &str) -> Self {
+        match self {
+            Error::SourceError(mut source_error) => {
+                source_error.quote(source);
+                Error::SourceError(source_error)
+            }
+            Error::Multiple(errors) => {
+                Error::Multiple(errors.into_iter().map(|e| e.quoting(source)).collect())
+            }
+            error => error,
+        }
+    }
+
+    fn with_label(mut self, location: (usize, usize), message: String) -> Self {
+        if let Error::SourceError(ref mut source_error) = self {
+            source_error.labels.push((location, message));
+        }
+        self
+    }
+
+    fn with_note(mut self, kind: &'static str, note: String) -> Self {
+        if let Error::SourceError(ref mut source_error) = self {
+            source_error.notes.push((kind, note));
+        }
+        self
+    }
+
+    pub fn render(&self, colour: bool) -> String {
+        match self {
+            Error::FailedPathLoad(ref path) => {
+                format!("error: Failed to load source.\n --> {}", path)
+            }
+            Error::SourceError(ref source_error) => source_error.render(Severity::Error, colour),
+            Error::Multiple(ref errors) => {
+                let rendered: Vec<String> = errors.iter().map(|e| e.render(colour)).collect();
+                format!("{} errors:\n\n{}", errors.len(), rendered.join("\n\n"))
+            }
+        }
+    }
+
+    pub fn errors(&self) -> &[Error] {
+        match self {
+            Error::Multiple(ref errors) => errors,
+            error => std::slice::from_ref(error),
+        }
+    }
 }
 
+// The exception the error is raised as, see exceptions::to_exception.
 impl ToPyObject for Error {
-    type ObjectType = PyString;
+    type ObjectType = PyObject;
 
     fn to_py_object(&self, py: Python) -> Self::ObjectType {
-        PyString::new(py, "Error")
+        exceptions::to_exception(py, self, false).unwrap_or_else(|mut err| err.instance(py))
     }
 }
 
-impl ToString for Error {
-    fn to_string(&self) -> String {
-        match self {
-            Error::PestError(ref msg) => {
-                let msg = format!("Parse error: {}", msg);
-                println!("Error: {}", msg);
-                msg
-            },
-            Error::FailedPathLoad => "Failed to load source.".to_string(),
-            Error::SourceError(ref source_error) => source_error.to_string(),
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+pub use crate::parser::exceptions::{add_exceptions, to_exception, warn};
+pub use crate::parser::targets::{Header, Target};
+
+pub struct Warning(SourceError);
+
+impl Warning {
+    pub fn render(&self, colour: bool) -> String {
+        self.0.render(Severity::Warning, colour)
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+pub struct Options {
+    pub defines: HashSet<String>,
+    pub profile: Profile,
+    pub signatures: Signatures,
+    pub modules: HashSet<String>,
+    pub colour: bool, // Whether errors and warnings are rendered with ANSI colours
+    pub warnings_as_errors: bool,
+    warnings: RefCell<Vec<Warning>>,
+}
+
+impl Options {
+    pub fn new(
+        defines: Vec<String>,
+        target: Target,
+        header: Header,
+        modules: &[String],
+        signatures: Option<&str>, // Overriding the built in signatures
+        colour: bool,
+        warnings_as_errors: bool,
+    ) -> Self {
+        Options {
+            defines: defines.into_iter().collect(),
+            profile: Profile::new(target, &header),
+            signatures: Signatures::new(&header.source, signatures),
+            modules: modules.iter().cloned().collect(),
+            colour,
+            warnings_as_errors,
+            warnings: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        let mut warnings: Vec<Warning> = self.warnings.borrow_mut().drain(..).collect();
+        warnings.sort_by(|Warning(a), Warning(b)| {
+            (&a.path, a.location).cmp(&(&b.path, b.location))
+        });
+        warnings
+    }
+}
+
+// Globals can only be judged once every file of the project has been parsed, which the caller
+// says by calling this. What's shared between the files is then cleared for the next build.
+pub fn finish_project() -> Vec<Warning> {
+    let mut project = std::mem::take(&mut *project::lock());
+
+    let mut warnings = vec![];
+    for (name, usage) in project.globals.take_single_use() {
+        let (code, description) = if usage.assigned {
+            (Code::UnusedVariable, format!("Global '{}' is assigned but never used.", name))
+        }
+        else {
+            let description =
+                format!("Global '{}' is only referenced once and never assigned.", name);
+            (Code::UnassignedVariable, description)
+        };
+        warnings.push(Warning(SourceError {
+            path: Some(usage.path),
+            lines: vec![(usage.location.0, usage.line)],
+            ..SourceError::new(usage.location, code, description)
+        }));
+    }
+    for (call, registers) in project.calls.take_clashes() {
+        let registers: Vec<String> = registers.iter().map(Register::to_string).collect();
+        let description = format!(
+            "'script_{}', or a script it calls, also uses {}, which an `auto` alias here holds.",
+            call.script,
+            registers.join(", ")
+        );
+        warnings.push(Warning(SourceError {
+            path: Some(call.path),
+            lines: vec![(call.location.0, call.line)],
+            ..SourceError::new(call.location, Code::RegisterClash, description)
+        }));
+    }
+    warnings.sort_by(|Warning(a), Warning(b)| (&a.path, a.location).cmp(&(&b.path, b.location)));
+    warnings
+}
+
+pub fn parse(
+    py: Python,
+    path: &str,
+    name: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<ast::Script, Error> {
+    let (text, source, syntax_errors) = read_source(path, Rule::main, options)?;
+    let pairs = parse_source(Rule::main, &source)?;
+    let mut context = Context::new(py, globals, options, path, text, &pairs, syntax_errors);
+    context.script = Some(name.to_string());
+
+    let mut statements = ast::StatementBlock::new();
+    parse_items(pairs, &mut statements, &mut context);
+    let result = context.check_try_blocks_closed();
+    context.recover(result);
+    context.end_block();
+    context.check_never_fails();
+
+    context.finish(ast::Script::new(name, statements))
+}
+
+pub fn parse_triggers(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::Trigger>, Error> {
+    parse_definitions(py, path, globals, options, Rule::triggers_file, definitions::parse_trigger)
+}
+
+pub fn parse_simple_triggers(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::SimpleTrigger>, Error> {
+    parse_definitions(
+        py,
+        path,
+        globals,
+        options,
+        Rule::simple_triggers_file,
+        definitions::parse_simple_trigger,
+    )
+}
+
+// Mission template triggers share the trigger tuple layout.
+pub fn parse_mission_triggers(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::Trigger>, Error> {
+    parse_definitions(
+        py,
+        path,
+        globals,
+        options,
+        Rule::mission_triggers_file,
+        definitions::parse_trigger,
+    )
+}
+
+pub fn parse_dialogs(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::Dialog>, Error> {
+    parse_definitions(py, path, globals, options, Rule::dialogs_file, definitions::parse_dialog)
+}
+
+pub fn parse_menus(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::Menu>, Error> {
+    let (text, source, syntax_errors) = read_source(path, Rule::menus_file, options)?;
+    let pairs = parse_source(Rule::menus_file, &source)?;
+    let mut context = Context::new(py, globals, options, path, text, &pairs, syntax_errors);
+
+    // Collected up front so options can jump to menus declared further down the file.
+    let declared_menus = pairs
+        .clone()
+        .filter(|pair| pair.as_rule() == Rule::menu)
+        .map(|pair| {
+            let id = pair.into_inner().next().expect("Failed to get menu id.");
+            format!("mnu_{}", id.as_str())
+        })
+        .collect();
+    context.declared_menus = Some(declared_menus);
+
+    let menus = pairs
+        .filter(|pair| pair.as_rule() != Rule::EOI)
+        .filter_map(|pair| {
+            let result = definitions::parse_menu(pair, &mut context);
+            context.recover(result)
+        })
+        .collect();
+    context.end_block();
+
+    context.finish(menus)
+}
+
+pub fn parse_presentations(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::Presentation>, Error> {
+    parse_definitions(
+        py,
+        path,
+        globals,
+        options,
+        Rule::presentations_file,
+        definitions::parse_presentation,
+    )
+}
+
+pub fn parse_scene_prop_triggers(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::SimpleTrigger>, Error> {
+    parse_definitions(
+        py,
+        path,
+        globals,
+        options,
+        Rule::scene_prop_triggers_file,
+        definitions::parse_simple_trigger,
+    )
+}
+
+pub fn parse_item_triggers(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::SimpleTrigger>, Error> {
+    parse_definitions(
+        py,
+        path,
+        globals,
+        options,
+        Rule::item_triggers_file,
+        definitions::parse_simple_trigger,
+    )
+}
+
+fn parse_patches(
+    py: Python,
+    path: &str,
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<ast::ScriptPatch>, Error> {
+    parse_definitions(
+        py,
+        path,
+        globals,
+        options,
+        Rule::patches_file,
+        definitions::parse_script_patch,
+    )
+}
+
+// Parses every file of patches and finds the script each extends. Nothing is given back to be
+// applied unless every file parses and every script extended exists.
+pub fn find_patches(
+    py: Python,
+    scripts: &PyList,
+    paths: &[String],
+    globals: &PyDict,
+    options: &Options,
+) -> Result<Vec<(ast::ScriptPatch, usize)>, Error> {
+    let mut patches = vec![];
+    let mut errors = vec![];
+    for path in paths.iter() {
+        match parse_patches(py, path, globals, options) {
+            Ok(file_patches) => {
+                for patch in file_patches {
+                    match patch.find(py, scripts) {
+                        Some(index) => patches.push((patch, index)),
+                        None => errors.push(patch.not_found().in_file(path)),
+                    }
+                }
+            }
+            Err(error) => errors.push(error),
         }
     }
+    if errors.is_empty() {
+        Ok(patches)
+    }
+    else {
+        Err(Error::from_errors(errors))
+    }
 }
 
-pub fn parse<'a>(path: &str, name: &str, globals: &'a PyDict) -> Result<ast::Script<'a>, Error> {
+fn parse_definitions<'a, T>(
+    py: Python<'a>,
+    path: &str,
+    globals: &'a PyDict,
+    options: &'a Options,
+    rule: Rule,
+    parse_definition: fn(Pair<Rule>, &mut Context<'a>) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    let (text, source, syntax_errors) = read_source(path, rule, options)?;
+    let pairs = parse_source(rule, &source)?;
+    let mut context = Context::new(py, globals, options, path, text, &pairs, syntax_errors);
+
+    let definitions = pairs
+        .filter(|pair| pair.as_rule() != Rule::EOI)
+        .filter_map(|pair| {
+            let result = parse_definition(pair, &mut context);
+            context.recover(result)
+        })
+        .collect();
+    context.end_block();
+
+    context.finish(definitions)
+}
+
+fn parse_source(rule: Rule, source: &str) -> Result<Pairs<'_, Rule>, Error> {
     use pest::Parser;
 
-    let mut script = ast::Script::new(name);
-    let source = load_source(path)?;
+    ParserInner::parse(rule, source).map_err(recovery::syntax_error)
+}
+
+struct Context<'a> {
+    py: Python<'a>,
+    globals: &'a PyDict,
+    options: &'a Options,
+    scopes: Scopes,
+    types: HashMap<String, (Type, (usize, usize))>, // By emitted local name, with its declaration
+    param_count: i64,
+    aliases: HashMap<String, (Register, (usize, usize))>, // Keyed by name as written, e.g. ":x"
+    engine_registers: HashSet<Register>, // Those already warned about in this file
+    in_trigger: bool,                    // Set while parsing triggers or event handlers
+    allocator: Allocator,
+    format_registers: HashMap<RegisterKind, Vec<Register>>,
+    held: Vec<Register>, // Given to `auto` aliases so far
+    declared_menus: Option<HashSet<String>>,
+    path: String,
+    text: String, // The file as read, for quoting in diagnostics
+    locals: Locals,
+    script: Option<String>,               // Set while parsing a script or a patch to one
+    try_blocks: TryBlocks,
+    errors: Vec<Error>,
+    block_errors: usize, // How many errors there were when the current block began
+    blanked: bool,       // Whether statements were blanked out after syntax errors
+}
+
+impl<'a> Context<'a> {
+    fn new(
+        py: Python<'a>,
+        globals: &'a PyDict,
+        options: &'a Options,
+        path: &str,
+        text: String,
+        pairs: &Pairs<Rule>,
+        syntax_errors: Vec<Error>,
+    ) -> Self {
+        let mut context = Context {
+            py,
+            globals,
+            options,
+            scopes: Scopes::new(),
+            types: HashMap::new(),
+            param_count: 0,
+            aliases: HashMap::new(),
+            engine_registers: HashSet::new(),
+            in_trigger: false,
+            allocator: Allocator::default(),
+            format_registers: HashMap::new(),
+            held: vec![],
+            declared_menus: None,
+            path: path.to_string(),
+            text,
+            locals: Locals::default(),
+            script: None,
+            try_blocks: TryBlocks::default(),
+            blanked: !syntax_errors.is_empty(),
+            block_errors: syntax_errors.len(),
+            errors: syntax_errors,
+        };
+
+        // Registers written out anywhere in the file are off limits to its `auto` aliases.
+        for pair in pairs.clone().flatten() {
+            if let Some(register) = explicit_register(&pair) {
+                context.allocator.reserve(register);
+            }
+        }
+
+        context
+    }
+
+    // Each statement block of a definition gets its own locals, aliases last for the whole file.
+    fn begin_block(&mut self) {
+        self.end_block();
+        self.scopes = Scopes::new();
+        self.types.clear();
+        self.param_count = 0;
+        self.try_blocks = TryBlocks::default();
+        self.block_errors = self.errors.len();
+    }
+
+    // Usage is only reported for blocks without errors, as statements that were rejected or
+    // blanked out may be what assigns or reads a local.
+    fn end_block(&mut self) {
+        let (unused, unassigned) = self.locals.take_misused();
+        if self.blanked || self.errors.len() > self.block_errors {
+            return;
+        }
+        for (name, location) in unused {
+            let description = format!("Local ':{}' is assigned but never used.", name);
+            self.warn_at(location, Code::UnusedVariable, description);
+        }
+        for (name, location) in unassigned {
+            let description = format!("Local ':{}' is used before it's assigned.", name);
+            self.warn_at(location, Code::UnassignedVariable, description);
+        }
+    }
+
+    fn track_failures(
+        &mut self,
+        operation: &str,
+        parameters: &[ast::StatementParameter],
+        span: &Span,
+    ) {
+        if !self.options.profile.tracks_failures() {
+            return;
+        }
+        let failure = match (operation, parameters.first()) {
+            (
+                "call_script",
+                Some(ast::StatementParameter::StatementParamId(
+                    ast::StatementParameterId::ScriptId(ref called),
+                )),
+            ) if called.starts_with("cf_") => Failure::Call(called),
+            _ if self.options.profile.can_fail(operation) => Failure::Operation(operation),
+            _ => return,
+        };
+        if !self.try_blocks.fail() {
+            return;
+        }
+
+        let script = self.script.as_deref();
+        if let Some(description) = script.and_then(|script| misnamed_failure(script, &failure)) {
+            self.warn(span, Code::FailureNaming, description);
+        }
+    }
+
+    fn track_calls(&self, operation: &str, parameters: &[ast::StatementParameter], span: &Span) {
+        if let (
+            "call_script",
+            Some(ast::StatementParameter::StatementParamId(ast::StatementParameterId::ScriptId(
+                ref called,
+            ))),
+        ) = (operation, parameters.first())
+        {
+            let location = span.start_pos().line_col();
+            let call = Call {
+                path: self.path.clone(),
+                location,
+                line: quote_line(&self.text, location.0).unwrap_or_default(),
+                script: called.clone(),
+                held: self.held.clone(),
+            };
+            project::lock().calls.call(self.script.as_deref(), call);
+        }
+    }
+
+    fn track_try_blocks(&mut self, operation: &str, span: &Span) -> Result<(), Error> {
+        let location = span.start_pos().line_col();
+        self.try_blocks
+            .track(operation, location)
+            .map_err(|description| source_error(span, Code::TryBlock, description))
+    }
+
+    // Try blocks have to be closed within the script or definition block they're opened in.
+    fn check_try_blocks_closed(&self) -> Result<(), Error> {
+        let errors: Vec<Error> = self
+            .try_blocks
+            .unclosed()
+            .iter()
+            .map(|(operation, location)| {
+                let description = format!("{} is missing its try_end.", operation);
+                error_at(*location, Code::TryBlock, &description)
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(Error::from_errors(errors))
+        }
+    }
+
+    fn check_never_fails(&self) {
+        if !self.options.profile.tracks_failures() {
+            return;
+        }
+        let script = self.script.as_deref();
+        let try_blocks = &self.try_blocks;
+        if let Some(description) = script.and_then(|script| misnamed_success(script, try_blocks)) {
+            self.warn_file(Code::FailureNaming, description);
+        }
+    }
+
+    // Follows process_operations.py: the first operand of an lhs operation is assigned and
+    // every other use of a local is a read.
+    fn track_usage(
+        &mut self,
+        operation: &str,
+        parameters: &[ast::StatementParameter],
+        spans: &[Span],
+    ) {
+        let profile = &self.options.profile;
+        if !profile.tracks_assignments() {
+            return;
+        }
+
+        for (index, (parameter, span)) in parameters.iter().zip(spans.iter()).enumerate() {
+            let location = span.start_pos().line_col();
+            match parameter {
+                ast::StatementParameter::LocalVariable(ref emitted) => {
+                    let name = self.scopes.source(emitted);
+                    if index == 0 && profile.assigns_local(operation) {
+                        self.locals.assign(emitted, name, location);
+                    }
+                    else {
+                        self.locals.read(emitted, name, location);
+                    }
+                }
+                ast::StatementParameter::GlobalVariable(ref name) => {
+                    let assigned = index == 0 && profile.assigns_global(operation);
+                    let globals = &mut project::lock().globals;
+                    let name = format!("${}", name);
+                    let line = quote_line(&self.text, location.0).unwrap_or_default();
+                    globals.record(name, &self.path, location, &line, assigned);
+                }
+                ast::StatementParameter::AutoPrefixedGlobalVariable(ref name) => {
+                    let assigned = index == 0 && profile.assigns_global(operation);
+                    let globals = &mut project::lock().globals;
+                    let name = format!("$g_{}", name);
+                    let line = quote_line(&self.text, location.0).unwrap_or_default();
+                    globals.record(name, &self.path, location, &line, assigned);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Keeps the error to raise once the whole file has been checked, so parsing can carry on.
+    fn recover<T>(&mut self, result: Result<T, Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    fn finish<T>(&mut self, value: T) -> Result<T, Error> {
+        match self.errors.len() {
+            0 => Ok(value),
+            _ => {
+                let error = Error::from_errors(self.errors.drain(..).collect());
+                Err(error.in_file(&self.path).quoting(&self.text))
+            }
+        }
+    }
+
+    // For errors that can't be confirmed, such as a name from a module that isn't imported.
+    fn warn_error(&self, error: Error) {
+        if let Error::SourceError(source_error) = error.in_file(&self.path).quoting(&self.text) {
+            self.options.warnings.borrow_mut().push(Warning(*source_error));
+        }
+    }
+
+    fn warn(&self, span: &Span, code: Code, description: String) {
+        self.warn_at(span.start_pos().line_col(), code, description);
+    }
+
+    fn warn_at(&self, location: (usize, usize), code: Code, description: String) {
+        let warning = SourceError::new(location, code, description);
+        self.warn_error(Error::SourceError(Box::new(warning)));
+    }
+
+    fn warn_file(&self, code: Code, description: String) {
+        self.options.warnings.borrow_mut().push(Warning(SourceError {
+            path: Some(self.path.clone()),
+            ..SourceError::whole_file(code, description)
+        }));
+    }
+
+    fn lookup(&self, name: &str) -> Option<PyObject> {
+        self.globals.get_item(self.py, name)
+    }
+
+    // Also used for the operations statements expand to, so a missing header_operations import
+    // is reported against the line that needed it.
+    // Operations are what header_operations defines, whether the target has them or not, since
+    // anything else the modules define can't be used as one.
+    fn operation(&self, name: &str, span: &Span) -> Result<PyObject, Error> {
+        let profile = &self.options.profile;
+        match self.lookup(name) {
+            Some(operation) if profile.has_operation(name) || profile.lacks_operation(name) => {
+                Ok(operation)
+            }
+            _ => {
+                let description = format!("Unknown operation '{}'.", name);
+                let error = source_error(span, Code::UnknownOperation, &description);
+                match suggestions::did_you_mean(name, profile.operation_names()) {
+                    Some(hint) => Err(error.with_note("help", hint)),
+                    None => Err(error),
+                }
+            }
+        }
+    }
 
-    let pairs =
-        ParserInner::parse(Rule::main, &source).map_err(|e| Error::PestError(e.to_string()))?;
+    fn global_names(&self, prefix: &str) -> Vec<String> {
+        self.globals
+            .items(self.py)
+            .iter()
+            .filter_map(|(key, _)| key.extract::<String>(self.py).ok())
+            .filter(|key| key.starts_with(prefix))
+            .collect()
+    }
 
+    // Builds the error for a name that failed to resolve, suggesting globals sharing its prefix.
+    fn unknown_name(
+        &self,
+        span: &Span,
+        code: Code,
+        description: &str,
+        name: &str,
+        prefix: &str,
+    ) -> Error {
+        let error = source_error(span, code, description);
+        match suggestions::did_you_mean(name, self.global_names(prefix)) {
+            Some(hint) => error.with_note("help", hint),
+            None => error,
+        }
+    }
+
+    // Points back at where a local was given the type a type error is about.
+    fn explain_type(&self, error: Error, parameter: &ast::StatementParameter) -> Error {
+        match parameter {
+            ast::StatementParameter::LocalVariable(ref name) => match self.types.get(name) {
+                Some(&(declared, location)) => {
+                    error.with_label(location, format!("declared as a {} here", declared))
+                }
+                None => error,
+            },
+            _ => error,
+        }
+    }
+
+    // reg registers are names header_common defines, str and pos registers are plain numbers.
+    fn allocate(&mut self, kind: RegisterKind) -> Option<Register> {
+        let count = self.options.profile.target.register_count();
+        let mut project = project::lock();
+        let start = project.next_registers.get(&kind).map_or(count - 1, |next| next % count);
+        let register = self.allocator.allocate(kind, count, start)?;
+        project.next_registers.insert(kind, (register.1 + count - 1) % count);
+        Some(register)
+    }
+    fn register(&self, register: Register, span: &Span) -> Result<ast::StatementParameter, Error> {
+        if let Some(ref script) = self.script {
+            project::lock().calls.use_register(script, register);
+        }
+        match register {
+            Register(RegisterKind::Register, code) => {
+                let name = format!("reg{}", code);
+                let value = self.lookup(&name).ok_or_else(|| {
+                    let description = format!("Unknown register '{}'.", name);
+                    let note =
+                        format!("{} is looked up in the modules given, e.g. header_common.", name);
+                    source_error(span, Code::UnknownName, &description).with_note("note", note)
+                })?;
+                Ok(ast::StatementParameter::Register(code, value))
+            }
+            Register(RegisterKind::String, code) => {
+                Ok(ast::StatementParameter::StringRegister(code))
+            }
+            Register(RegisterKind::Position, code) => {
+                Ok(ast::StatementParameter::PositionRegister(code))
+            }
+        }
+    }
+
+    fn parameter_type(&self, parameter: &ast::StatementParameter) -> Option<Type> {
+        match parameter {
+            ast::StatementParameter::LocalVariable(ref name) => {
+                self.types.get(name).map(|&(declared, _)| declared)
+            }
+            _ => Type::of_literal(parameter),
+        }
+    }
+}
+
+fn register_kind(rule: Rule) -> Option<RegisterKind> {
+    match rule {
+        Rule::statement_parameter_register => Some(RegisterKind::Register),
+        Rule::statement_parameter_string_register => Some(RegisterKind::String),
+        Rule::statement_parameter_position_register => Some(RegisterKind::Position),
+        _ => None,
+    }
+}
+
+fn explicit_register(pair: &Pair<Rule>) -> Option<Register> {
+    let kind = register_kind(pair.as_rule())?;
+    let code = pair.as_str()[kind.prefix().len() + 1..].parse::<u8>().ok()?;
+    Some(Register(kind, code))
+}
+
+// Like explicit_register, but holds the register to the range the target has.
+fn parse_register(pair: &Pair<Rule>, context: &Context) -> Result<Register, Error> {
+    let kind = register_kind(pair.as_rule()).expect("Expected a register.");
+    let target = context.options.profile.target;
+    registers::parse_register(kind, pair.as_str(), target)
+        .map_err(|description| source_error(&pair.as_span(), Code::RegisterRange, &description))
+}
+
+fn source_error(span: &Span, code: Code, description: &str) -> Error {
+    Error::SourceError(Box::new(SourceError {
+        end: Some(span.end_pos().line_col()),
+        ..SourceError::new(span.start_pos().line_col(), code, description.to_string())
+    }))
+}
+
+fn quote_line(source: &str, number: usize) -> Option<String> {
+    source.lines().nth(number.checked_sub(1)?).map(str::to_string)
+}
+
+fn error_at(location: (usize, usize), code: Code, description: &str) -> Error {
+    Error::SourceError(Box::new(SourceError::new(location, code, description.to_string())))
+}
+
+// A statement with an error is left out and parsing moves on to the next one, so one mistake
+// doesn't hide the rest.
+fn parse_items<'a>(
+    pairs: Pairs<Rule>,
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) {
     for pair in pairs {
-        match pair.as_rule() {
-            Rule::statement => {
-                let mut inner = pair.into_inner();
-                let identifier: &str = inner
+        let result = parse_item(pair, statements, context);
+        context.recover(result);
+    }
+}
+
+fn parse_item<'a>(
+    pair: Pair<Rule>,
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) -> Result<(), Error> {
+    match pair.as_rule() {
+        Rule::statement => parse_statement(pair, statements, context)?,
+        Rule::let_statement => {
+            let statement = parse_let_statement(pair, context)?;
+            statements.push_statement(statement);
+        }
+        Rule::param_statement => {
+            let statement = parse_param_statement(pair, context)?;
+            statements.push_statement(statement);
+        }
+        Rule::alias_statement => parse_alias_statement(pair, context)?,
+        Rule::position_field_assignment => {
+            parse_position_field_assignment(pair, statements, context)?
+        }
+        Rule::position_field_read => {
+            let statement = parse_position_field_read(pair, context)?;
+            statements.push_statement(statement);
+        }
+        Rule::position_copy => {
+            let statement = parse_position_copy(pair, context)?;
+            statements.push_statement(statement);
+        }
+        Rule::block => {
+            context.scopes.push();
+            parse_items(pair.into_inner(), statements, context);
+            context.scopes.pop();
+        }
+        Rule::EOI => {}
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn parse_statement<'a>(
+    pair: Pair<Rule>,
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) -> Result<(), Error> {
+    let mut inner = pair.into_inner();
+    let identifier_pair = inner.next().expect("Failed to get statement identifier.");
+    let identifier = identifier_pair.as_str();
+    let identifier_span = identifier_pair.as_span();
+    let operation = context.operation(identifier, &identifier_span)?;
+    context.track_try_blocks(identifier, &identifier_span)?;
+    if context.options.profile.lacks_operation(identifier) {
+        let description = format!(
+            "Operation '{}' isn't available when targeting {}.",
+            identifier, context.options.profile.target
+        );
+        return Err(source_error(&identifier_pair.as_span(), Code::Unavailable, &description));
+    }
+    let mut statement_params: Vec<ast::StatementParameter> = vec![];
+    let mut spans = vec![];
+    let mut format_counts = HashMap::new();
+    for param in inner {
+        match param.as_rule() {
+            Rule::statement_parameter => {
+                spans.push(param.as_span());
+                let param_kind = param
+                    .clone()
+                    .into_inner()
+                    .next()
+                    .expect("Failed to get param kind.");
+                let parameter = match param_kind.as_rule() {
+                    Rule::statement_parameter_format_string => {
+                        parse_format_string(param_kind, &mut format_counts, statements, context)?
+                    }
+                    _ => parse_parameter(param, context)?,
+                };
+                statement_params.push(parameter);
+            }
+            _ => unreachable!(),
+        }
+    }
+    context.track_usage(identifier, &statement_params, &spans);
+    check_operand_kinds(identifier, &identifier_span, &statement_params, &spans, context)?;
+    check_assignment_types(identifier, &statement_params, &spans, context)?;
+    context.track_failures(identifier, &statement_params, &identifier_span);
+    context.track_calls(identifier, &statement_params, &identifier_span);
+    statements.push_statement(ast::Statement::new(operation, statement_params));
+    Ok(())
+}
+
+// Pushes the statements filling each placeholder's register ahead of the statement using
+// the format string. Registers come from a pool shared by every format string in the file,
+// indexed by how many of that kind the current statement has used so far.
+fn parse_format_string<'a>(
+    pair: Pair<Rule>,
+    format_counts: &mut HashMap<RegisterKind, usize>,
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) -> Result<ast::StatementParameter, Error> {
+    let mut text = String::from("@");
+
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            Rule::format_text => text.push_str(part.as_str()),
+            Rule::format_escape => text.push_str(&part.as_str()[1..]),
+            Rule::format_placeholder => {
+                let param = part
+                    .into_inner()
                     .next()
-                    .expect("Failed to get statement identifier.")
-                    .as_str();
-                let mut statement_params: Vec<ast::StatementParameter> = vec![];
-                while let Some(param) = inner.next() {
-                    match param.as_rule() {
-                        Rule::statement_parameter => {
-                            let param_kind = param
-                                .into_inner()
-                                .next()
-                                .expect("Failed to get param kind.");
-                            let span = param_kind.as_span();
-                            match param_kind.as_rule() {
-                                Rule::statement_parameter_number => {
-                                    let numstr = param_kind.as_str();
-                                    let num = numstr.parse::<i64>().map_err(|_| {
-                                        Error::SourceError(SourceError {
-                                            location: span.start_pos().line_col(),
-                                            description: "Invalid parameter number.".to_string(),
-                                        })
-                                    })?;
-                                    statement_params.push(ast::StatementParameter::Number(num));
-                                }
-                                Rule::statement_parameter_string_register => {
-                                    let string_reg = param_kind.as_str();
-                                    let num_section = &string_reg["str.".len()..];
-                                    let code = num_section.parse::<u8>().map_err(|_| {
-                                        Error::SourceError(SourceError {
-                                            location: span.start_pos().line_col(),
-                                            description: "Invalid string register.".to_string(),
-                                        })
-                                    })?;
-                                    statement_params
-                                        .push(ast::StatementParameter::StringRegister(code));
-                                }
-                                Rule::statement_parameter_register => {
-                                    let reg = param_kind.as_str();
-                                    let num_section = &reg["reg.".len()..];
-                                    let code = num_section.parse::<u8>().map_err(|_| {
-                                        Error::SourceError(SourceError {
-                                            location: span.start_pos().line_col(),
-                                            description: "Invalid register.".to_string(),
-                                        })
-                                    })?;
-                                    statement_params.push(ast::StatementParameter::Register(code, globals));
-                                }
-                                Rule::statement_parameter_position_register => {
-                                    let pos_reg = param_kind.as_str();
-                                    let num_section = &pos_reg["pos.".len()..];
-                                    let code = num_section.parse::<u8>().map_err(|_| {
-                                        Error::SourceError(SourceError {
-                                            location: span.start_pos().line_col(),
-                                            description: "Invalid position register.".to_string(),
-                                        })
-                                    })?;
-                                    statement_params
-                                        .push(ast::StatementParameter::PositionRegister(code));
-                                }
-                                Rule::statement_parameter_local_var => {
-                                    let var = param_kind
-                                        .into_inner()
-                                        .next()
-                                        .expect("Failed to get local variable.")
-                                        .as_str();
-                                    statement_params.push(ast::StatementParameter::LocalVariable(
-                                        var.to_string(),
-                                    ));
-                                }
-                                Rule::statement_parameter_global_var => {
-                                    let var = param_kind
-                                        .into_inner()
-                                        .next()
-                                        .expect("Failed to get global variable.")
-                                        .as_str();
-                                    statement_params.push(ast::StatementParameter::GlobalVariable(
-                                        var.to_string(),
-                                    ));
-                                }
-                                Rule::statement_parameter_autoprefixed_global_var => {
-                                    let var = param_kind
-                                        .into_inner()
-                                        .next()
-                                        .expect("Failed to get global variable.")
-                                        .as_str();
-                                    statement_params.push(
-                                        ast::StatementParameter::AutoPrefixedGlobalVariable(
-                                            var.to_string(),
-                                        ),
-                                    );
-                                }
-                                Rule::statement_parameter_id => {
-                                    let param_id = param_kind
-                                        .into_inner()
-                                        .next()
-                                        .expect("Failed to get id parameter.");
-
-                                    let kind = match param_id.as_rule() {
-                                        Rule::animation_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::AnimationId(text)
-                                        }
-                                        Rule::faction_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::FactionId(text)
-                                        }
-                                        Rule::info_page_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::InfoPageId(text)
-                                        }
-                                        Rule::item_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::ItemId(text)
-                                        }
-                                        Rule::map_icon_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::MapIconId(text)
-                                        }
-                                        Rule::game_menu_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::GameMenuId(text)
-                                        }
-                                        Rule::mesh_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::MeshId(text)
-                                        }
-                                        Rule::mission_template_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::MissionTemplateId(text)
-                                        }
-                                        Rule::particle_system_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::ParticleSystemId(text)
-                                        }
-                                        Rule::party_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::PartyId(text)
-                                        }
-                                        Rule::party_template_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::PartyTemplateId(text)
-                                        }
-                                        Rule::postfx_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::PostfxId(text)
-                                        }
-                                        Rule::presentation_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::PresentationId(text)
-                                        }
-                                        Rule::quest_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::QuestId(text)
-                                        }
-                                        Rule::scene_prop_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::ScenePropId(text)
-                                        }
-                                        Rule::scene_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::SceneId(text)
-                                        }
-                                        Rule::script_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::ScriptId(text)
-                                        }
-                                        Rule::skill_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::SkillId(text)
-                                        }
-                                        Rule::sound_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::SoundId(text)
-                                        }
-                                        Rule::string_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::StringId(text)
-                                        }
-                                        Rule::tableau_material_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::TableauMaterialId(text)
-                                        }
-                                        Rule::troop_id => {
-                                            let text = param_id
-                                                .into_inner()
-                                                .next()
-                                                .expect("Failed to get inner id parameter.")
-                                                .as_str()
-                                                .to_string();
-                                            ast::StatementParameterId::TroopId(text)
-                                        }
-                                        _ => unreachable!(),
-                                    };
-                                    statement_params
-                                        .push(ast::StatementParameter::StatementParamId(kind));
-                                }
-                                Rule::identifier => {
-                                    let id = param_kind.as_str();
-                                    statement_params.push(ast::StatementParameter::Identifier(
-                                        id.to_string(),
-                                        &globals,
-                                    ));
-                                }
-                                _ => unreachable!(),
-                            }
+                    .expect("Failed to get placeholder parameter.");
+                let span = param.as_span();
+                let value = parse_parameter(param, context)?;
+
+                let store_operation = match value {
+                    ast::StatementParameter::StringRegister(code) => {
+                        text.push_str(&format!("{{s{}}}", code));
+                        continue;
+                    }
+                    ast::StatementParameter::Register(code, _) => {
+                        text.push_str(&format!("{{reg{}}}", code));
+                        continue;
+                    }
+                    ast::StatementParameter::StatementParamId(
+                        ast::StatementParameterId::StringId(_),
+                    ) => Some("str_store_string"),
+                    _ => match context.parameter_type(&value) {
+                        Some(Type::Agent) => Some("str_store_agent_name"),
+                        Some(Type::Faction) => Some("str_store_faction_name"),
+                        Some(Type::Item) => Some("str_store_item_name"),
+                        Some(Type::Party) => Some("str_store_party_name"),
+                        Some(Type::Troop) => Some("str_store_troop_name"),
+                        Some(other) => {
+                            return Err(source_error(
+                                &span,
+                                Code::TypeMismatch,
+                                &format!("Cannot format a {} into text.", other),
+                            ))
                         }
-                        _ => unreachable!(),
+                        None => None,
+                    },
+                };
+
+                let kind = match store_operation {
+                    Some(_) => RegisterKind::String,
+                    None => RegisterKind::Register,
+                };
+                let register = format_register(kind, format_counts, context).ok_or_else(|| {
+                    source_error(
+                        &span,
+                        Code::RegisterRange,
+                        &format!("No free {} registers left to format with.", kind.prefix()),
+                    )
+                })?;
+                let Register(_, code) = register;
+                let target = context.register(register, &span)?;
+
+                let operation = match store_operation {
+                    Some(operation) => {
+                        text.push_str(&format!("{{s{}}}", code));
+                        operation
                     }
-                }
-                let statement =
-                    ast::Statement::new(identifier.to_string(), statement_params, &globals);
-                script.push_statement(statement);
+                    None => {
+                        text.push_str(&format!("{{reg{}}}", code));
+                        "assign"
+                    }
+                };
+                let parameters = vec![target, value];
+                context.track_usage(operation, &parameters, &[span.clone(), span.clone()]);
+                let operation = context.operation(operation, &span)?;
+                statements.push_statement(ast::Statement::new(operation, parameters));
             }
-            Rule::EOI => {}
             _ => unreachable!(),
         }
     }
 
-    Ok(script)
+    Ok(ast::StatementParameter::String(text))
+}
+
+fn format_register(
+    kind: RegisterKind,
+    format_counts: &mut HashMap<RegisterKind, usize>,
+    context: &mut Context,
+) -> Option<Register> {
+    let index = format_counts.entry(kind).or_insert(0);
+    if *index == context.format_registers.get(&kind).map_or(0, Vec::len) {
+        let register = context.allocate(kind)?;
+        context.format_registers.entry(kind).or_default().push(register);
+    }
+
+    let register = context.format_registers[&kind][*index];
+    *index += 1;
+    Some(register)
+}
+
+fn parse_let_statement<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Statement, Error> {
+    let operation = context.operation("assign", &pair.as_span())?;
+    let mut inner = pair.into_inner();
+    let declaration = inner.next().expect("Failed to get let declaration.");
+    let declaration_span = declaration.as_span();
+    let value_pair = inner.next().expect("Failed to get let value.");
+    let value_span = value_pair.as_span();
+
+    // The value is resolved before the declaration so `let :i = :i;` reads the outer :i.
+    let value = parse_parameter(value_pair, context)?;
+    let (local, declared_type) = parse_declaration(declaration, context)?;
+
+    if let (Some(expected), Some(found)) = (declared_type, context.parameter_type(&value)) {
+        if expected != found {
+            let error = source_error(
+                &value_span,
+                Code::TypeMismatch,
+                &format!("Expected a {} but was given a {}.", expected, found),
+            );
+            return Err(context.explain_type(error, &value));
+        }
+    }
+
+    let parameters = vec![local, value];
+    context.track_usage("assign", &parameters, &[declaration_span, value_span]);
+    Ok(ast::Statement::new(operation, parameters))
+}
+
+fn parse_param_statement<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Statement, Error> {
+    let span = pair.as_span();
+    let operation = context.operation("store_script_param", &span)?;
+    let declaration = pair
+        .into_inner()
+        .next()
+        .expect("Failed to get param declaration.");
+    let (local, _) = parse_declaration(declaration, context)?;
+
+    context.param_count += 1;
+
+    let parameters = vec![local, ast::StatementParameter::Number(context.param_count)];
+    context.track_usage("store_script_param", &parameters, &[span.clone(), span]);
+    Ok(ast::Statement::new(operation, parameters))
+}
+
+fn parse_alias_statement(pair: Pair<Rule>, context: &mut Context) -> Result<(), Error> {
+    let mut inner = pair.into_inner();
+    let name_pair = inner.next().expect("Failed to get alias name.");
+    let name_span = name_pair.as_span();
+    let name = name_pair.as_str().to_string();
+    let target = inner
+        .next()
+        .expect("Failed to get alias target.")
+        .into_inner()
+        .next()
+        .expect("Failed to get alias register.");
+    let target_span = target.as_span();
+
+    if let Some(&(_, location)) = context.aliases.get(&name) {
+        let description = format!("Alias '{}' is already declared.", name);
+        let error = source_error(&name_span, Code::Redeclared, &description);
+        return Err(error.with_label(location, "first declared here".to_string()));
+    }
+
+    let register = match target.as_rule() {
+        Rule::alias_auto => {
+            let kind_str = target
+                .into_inner()
+                .next()
+                .expect("Failed to get alias register kind.")
+                .as_str();
+            let kind = RegisterKind::from_prefix(kind_str).expect("Unknown register kind.");
+            let register = context.allocate(kind).ok_or_else(|| {
+                source_error(
+                    &target_span,
+                    Code::RegisterRange,
+                    &format!("No free {} registers left to allocate.", kind.prefix()),
+                )
+            })?;
+            context.held.push(register);
+            register
+        }
+        _ => parse_register(&target, context)?,
+    };
+    if ENGINE_REGISTERS.contains(&register) {
+        let description = format!(
+            "{} is overwritten by the engine before some triggers run, \
+             so it may not keep its value under an alias.",
+            register
+        );
+        context.warn(&target_span, Code::EngineRegister, description);
+    }
+
+    // Checked here so a missing reg<N> is reported once, at the alias rather than each use.
+    context.register(register, &target_span)?;
+
+    if let Some((other, &(_, location))) = context.aliases.iter().find(|(_, a)| a.0 == register) {
+        let error = source_error(
+            &target_span,
+            Code::RegisterAliased,
+            &format!("Register {} is already aliased as '{}'.", register, other),
+        );
+        return Err(error.with_label(location, format!("'{}' is declared here", other)));
+    }
+
+    context.aliases.insert(name, (register, name_span.start_pos().line_col()));
+    Ok(())
+}
+
+fn parse_position_operand<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::StatementParameter, Error> {
+    let span = pair.as_span();
+    let operand = pair
+        .into_inner()
+        .next()
+        .expect("Failed to get position operand.");
+    match parse_parameter_kind(operand, context)? {
+        position @ ast::StatementParameter::PositionRegister(_) => Ok(position),
+        _ => Err(source_error(
+            &span,
+            Code::OperandKind,
+            "Expected a position register or position alias.",
+        )),
+    }
+}
+
+// Returns the position operand and the field name, e.g. "x" or "rot_z".
+fn parse_position_field_access<'a, 'i>(
+    pair: Pair<'i, Rule>,
+    context: &mut Context<'a>,
+) -> Result<(ast::StatementParameter, &'i str), Error> {
+    let mut inner = pair.into_inner();
+    let position = parse_position_operand(inner.next().expect("Failed to get position."), context)?;
+    let field = inner.next().expect("Failed to get position field.").as_str();
+    Ok((position, field))
+}
+
+fn parse_position_field_assignment<'a>(
+    pair: Pair<Rule>,
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) -> Result<(), Error> {
+    let mut inner = pair.into_inner();
+    let access = inner.next().expect("Failed to get position field.");
+    let access_span = access.as_span();
+    let (position, field) = parse_position_field_access(access, context)?;
+    let operator = inner.next().expect("Failed to get assignment operator.").as_str();
+    let value_pair = inner.next().expect("Failed to get assigned value.");
+    let value_span = value_pair.as_span();
+    let value = parse_parameter(value_pair, context)?;
+    let spans = [access_span.clone(), value_span.clone()];
+
+    // Rotations can only be applied relative to the current one.
+    let axis = match (operator, field.strip_prefix("rot_")) {
+        ("=", Some(_)) => {
+            return Err(source_error(
+                &access_span,
+                Code::OperandKind,
+                "Rotations can't be assigned, use += or -= to rotate.",
+            ))
+        }
+        ("=", None) => {
+            let operation = format!("position_set_{}", field);
+            return push_statement(&operation, vec![position, value], &spans, statements, context);
+        }
+        (_, Some(axis)) => axis,
+        (_, None) => {
+            let change = (field, operator, value);
+            return parse_coordinate_change(position, change, &spans, statements, context);
+        }
+    };
+
+    let value = match (operator, value) {
+        ("-=", ast::StatementParameter::Number(num)) => ast::StatementParameter::Number(-num),
+        ("-=", _) => {
+            let description = "-= on a rotation can only be used with a number.";
+            return Err(source_error(&value_span, Code::OperandKind, description));
+        }
+        (_, value) => value,
+    };
+    let operation = format!("position_rotate_{}", axis);
+    push_statement(&operation, vec![position, value], &spans, statements, context)
+}
+
+// Coordinates are read into a spare register, added to and written back, since position_move_*
+// moves along the position's own rotated axis rather than the world's.
+fn parse_coordinate_change<'a>(
+    position: ast::StatementParameter,
+    (field, operator, value): (&str, &str, ast::StatementParameter),
+    spans: &[Span],
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) -> Result<(), Error> {
+    let code = match position {
+        ast::StatementParameter::PositionRegister(code) => code,
+        _ => unreachable!(),
+    };
+    let register = format_register(RegisterKind::Register, &mut HashMap::new(), context);
+    let register = register.ok_or_else(|| {
+        source_error(&spans[0], Code::RegisterRange, "No free reg registers left to use.")
+    })?;
+    let arithmetic = match operator {
+        "+=" => "val_add",
+        _ => "val_sub",
+    };
+
+    let get = format!("position_get_{}", field);
+    let temporary = context.register(register, &spans[0])?;
+    let parameters = vec![temporary, ast::StatementParameter::PositionRegister(code)];
+    push_statement(&get, parameters, spans, statements, context)?;
+    let temporary = context.register(register, &spans[0])?;
+    let parameters = vec![temporary, value];
+    push_statement(arithmetic, parameters, spans, statements, context)?;
+    let set = format!("position_set_{}", field);
+    let temporary = context.register(register, &spans[0])?;
+    let parameters = vec![ast::StatementParameter::PositionRegister(code), temporary];
+    push_statement(&set, parameters, spans, statements, context)
+}
+
+// For statements a line of mbs expands to, with the spans of the text they came from.
+fn push_statement<'a>(
+    operation: &str,
+    parameters: Vec<ast::StatementParameter>,
+    spans: &[Span],
+    statements: &mut ast::StatementBlock,
+    context: &mut Context<'a>,
+) -> Result<(), Error> {
+    context.track_usage(operation, &parameters, spans);
+    let operation = context.operation(operation, &spans[0])?;
+    statements.push_statement(ast::Statement::new(operation, parameters));
+    Ok(())
+}
+
+fn parse_position_field_read<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Statement, Error> {
+    let mut inner = pair.into_inner();
+    let target_pair = inner.next().expect("Failed to get target.");
+    let target_span = target_pair.as_span();
+    let target = parse_parameter(target_pair, context)?;
+    let access = inner.next().expect("Failed to get position field.");
+    let access_span = access.as_span();
+    let (position, field) = parse_position_field_access(access, context)?;
+
+    let operation = match field.strip_prefix("rot_") {
+        Some(axis) => format!("position_get_rotation_around_{}", axis),
+        None => format!("position_get_{}", field),
+    };
+    let parameters = vec![target, position];
+    context.track_usage(&operation, &parameters, &[target_span, access_span.clone()]);
+    let operation = context.operation(&operation, &access_span)?;
+    Ok(ast::Statement::new(operation, parameters))
+}
+
+fn parse_position_copy<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::Statement, Error> {
+    let operation = context.operation("copy_position", &pair.as_span())?;
+    let mut inner = pair.into_inner();
+    let target = parse_position_operand(inner.next().expect("Failed to get target."), context)?;
+    let source = parse_position_operand(inner.next().expect("Failed to get source."), context)?;
+
+    Ok(ast::Statement::new(operation, vec![target, source]))
+}
+
+fn parse_declaration<'a>(
+    pair: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<(ast::StatementParameter, Option<Type>), Error> {
+    let mut inner = pair.into_inner();
+    let local = inner.next().expect("Failed to get declared variable.");
+    let span = local.as_span();
+    let name = local
+        .into_inner()
+        .next()
+        .expect("Failed to get local variable.")
+        .as_str();
+
+    let declared_type = match inner.next() {
+        Some(annotation) => {
+            let type_span = annotation.as_span();
+            let type_name = annotation
+                .into_inner()
+                .next()
+                .expect("Failed to get type name.")
+                .as_str();
+            let declared_type = Type::from_name(type_name).ok_or_else(|| {
+                let description = format!("Unknown type '{}'.", type_name);
+                source_error(&type_span, Code::UnknownType, &description)
+            })?;
+            Some(declared_type)
+        }
+        None => None,
+    };
+
+    if context.aliases.contains_key(&format!(":{}", name)) {
+        let description = "Local variable is already declared as an alias.";
+        return Err(source_error(&span, Code::Redeclared, description));
+    }
+
+    let emitted = context.scopes.declare(name).map_err(|e| match e {
+        ScopeError::AlreadyDeclared => {
+            source_error(&span, Code::Redeclared, "Local variable already declared in this block.")
+        }
+        ScopeError::OutOfScope => unreachable!(),
+    })?;
+    if emitted != name {
+        let description = format!("Local ':{}' shadows another local of the same name.", name);
+        context.warn(&span, Code::Shadowed, description);
+    }
+
+    match declared_type {
+        Some(declared_type) => {
+            let location = span.start_pos().line_col();
+            context.types.insert(emitted.clone(), (declared_type, location))
+        }
+        None => context.types.remove(&emitted),
+    };
+
+    Ok((ast::StatementParameter::LocalVariable(emitted), declared_type))
+}
+
+fn check_operand_kinds(
+    operation: &str,
+    operation_span: &Span,
+    parameters: &[ast::StatementParameter],
+    spans: &[Span],
+    context: &Context,
+) -> Result<(), Error> {
+    let signature = match context.options.signatures.get(operation) {
+        Some(signature) => signature,
+        None => return Ok(()),
+    };
+
+    if !signature.accepts_count(parameters.len()) {
+        return Err(source_error(
+            operation_span,
+            Code::OperandCount,
+            &format!(
+                "'{}' expects {} but was given {}.",
+                operation,
+                signature.describe_count(),
+                parameters.len()
+            ),
+        ));
+    }
+
+    for (index, parameter) in parameters.iter().enumerate() {
+        let kind = match signature.operand(index) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let declared = context.parameter_type(parameter);
+        if kind.accepts(parameter, declared) {
+            continue;
+        }
+
+        let (code, given) = match (parameter, declared) {
+            (ast::StatementParameter::LocalVariable(_), Some(declared)) => {
+                (Code::TypeMismatch, format!("a {}", declared))
+            }
+            _ => (Code::OperandKind, signatures::describe(parameter)),
+        };
+        let error = source_error(
+            &spans[index],
+            code,
+            &format!(
+                "Operand {} of '{}' expects {} but was given {}.",
+                index + 1,
+                operation,
+                kind,
+                given
+            ),
+        );
+        return Err(context.explain_type(error, parameter));
+    }
+
+    Ok(())
+}
+
+// Operands are checked against their declared types along with their kinds, which leaves
+// assigning one typed local to another.
+fn check_assignment_types(
+    operation: &str,
+    parameters: &[ast::StatementParameter],
+    spans: &[Span],
+    context: &Context,
+) -> Result<(), Error> {
+    if operation != "assign" {
+        return Ok(());
+    }
+
+    let found: Vec<Option<Type>> = parameters
+        .iter()
+        .map(|p| context.parameter_type(p))
+        .collect();
+    if let (Some(Some(target)), Some(Some(value))) = (found.first(), found.get(1)) {
+        if target != value {
+            let error = source_error(
+                &spans[1],
+                Code::TypeMismatch,
+                &format!("Cannot assign a {} to a {}.", value, target),
+            );
+            let error = context.explain_type(error, &parameters[0]);
+            return Err(context.explain_type(error, &parameters[1]));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_parameter<'a>(
+    param: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::StatementParameter, Error> {
+    let param_kind = param.into_inner().next().expect("Failed to get param kind.");
+    parse_parameter_kind(param_kind, context)
+}
+
+fn parse_parameter_kind<'a>(
+    param_kind: Pair<Rule>,
+    context: &mut Context<'a>,
+) -> Result<ast::StatementParameter, Error> {
+    let span = param_kind.as_span();
+    let parameter = match param_kind.as_rule() {
+        Rule::statement_parameter_number => {
+            let numstr = param_kind.as_str();
+            let num = numstr
+                .parse::<i64>()
+                .map_err(|_| {
+                    source_error(&span, Code::InvalidNumber, "Invalid parameter number.")
+                })?;
+            ast::StatementParameter::Number(num)
+        }
+        Rule::statement_parameter_string_register
+        | Rule::statement_parameter_register
+        | Rule::statement_parameter_position_register => {
+            let register = parse_register(&param_kind, context)?;
+            // Outside of triggers these are just the usual registers for passing values back.
+            if ENGINE_REGISTERS.contains(&register)
+                && context.in_trigger
+                && context.engine_registers.insert(register)
+            {
+                let description = format!(
+                    "{} is overwritten by the engine before some triggers run, \
+                     so a value left in it may be lost.",
+                    register
+                );
+                context.warn(&span, Code::EngineRegister, description);
+            }
+            context.register(register, &span)?
+        }
+        Rule::statement_parameter_local_var => {
+            if let Some(&(register, _)) = context.aliases.get(param_kind.as_str()) {
+                return context.register(register, &span);
+            }
+            let var = param_kind
+                .into_inner()
+                .next()
+                .expect("Failed to get local variable.")
+                .as_str();
+            let emitted = context.scopes.resolve(var).map_err(|e| match e {
+                ScopeError::OutOfScope => {
+                    let description = "Local variable used outside of the block declaring it.";
+                    source_error(&span, Code::OutOfScope, description)
+                }
+                ScopeError::AlreadyDeclared => unreachable!(),
+            })?;
+            ast::StatementParameter::LocalVariable(emitted)
+        }
+        Rule::statement_parameter_global_var => {
+            let var = param_kind
+                .into_inner()
+                .next()
+                .expect("Failed to get global variable.")
+                .as_str();
+            ast::StatementParameter::GlobalVariable(var.to_string())
+        }
+        Rule::statement_parameter_autoprefixed_global_var => {
+            let var = param_kind
+                .into_inner()
+                .next()
+                .expect("Failed to get global variable.")
+                .as_str();
+            ast::StatementParameter::AutoPrefixedGlobalVariable(var.to_string())
+        }
+        Rule::statement_parameter_id => {
+            let param_id = param_kind
+                .into_inner()
+                .next()
+                .expect("Failed to get id parameter.");
+            if param_id.as_str().starts_with("menu.") {
+                let description = "The 'menu.' prefix is deprecated, use 'mnu.' instead.";
+                context.warn(&span, Code::Deprecated, description.to_string());
+            }
+            let id = parse_id(param_id);
+            check_id(&id, &span, context)?;
+            ast::StatementParameter::StatementParamId(id)
+        }
+        Rule::statement_parameter_string => {
+            let text = param_kind
+                .into_inner()
+                .next()
+                .expect("Failed to get string text.")
+                .as_str();
+            ast::StatementParameter::String(text.to_string())
+        }
+        Rule::statement_parameter_format_string => {
+            return Err(source_error(
+                &span,
+                Code::OperandKind,
+                "Format strings can only be used as statement operands.",
+            ))
+        }
+        Rule::identifier => {
+            let id = param_kind.as_str();
+            match context.aliases.get(id) {
+                Some(&(register, _)) => context.register(register, &span)?,
+                None => match context.lookup(id) {
+                    Some(value) => ast::StatementParameter::Identifier(value),
+                    None => {
+                        let description = format!("Unknown identifier '{}'.", id);
+                        let code = Code::UnknownName;
+                        return Err(context.unknown_name(&span, code, &description, id, ""));
+                    }
+                },
+            }
+        }
+        _ => unreachable!(),
+    };
+
+    Ok(parameter)
+}
+
+// Ids are left for the module system to resolve, but can be checked here when their ID_ module
+// was imported, or when they're menus declared alongside each other.
+fn check_id(id: &ast::StatementParameterId, span: &Span, context: &Context) -> Result<(), Error> {
+    let name = id.name();
+    if context.lookup(&name).is_some() {
+        return Ok(());
+    }
+
+    let prefix = &name[..=name.find('_').expect("Failed to get id prefix.")];
+    let imported = context.options.modules.contains(id.module());
+    match (id, &context.declared_menus) {
+        // ID_menus comes out of the same build, so without it the menu may well exist.
+        (ast::StatementParameterId::GameMenuId(_), Some(declared_menus))
+            if !declared_menus.contains(&name) =>
+        {
+            let candidates = context
+                .global_names(prefix)
+                .into_iter()
+                .chain(declared_menus.iter().cloned());
+            let (code, description) = if imported {
+                (Code::UnknownId, format!("Unknown menu '{}'.", name))
+            }
+            else {
+                let description = format!(
+                    "Menu '{}' is not declared in this file, and ID_menus isn't imported to \
+                     check it against.",
+                    name
+                );
+                (Code::UncheckedMenu, description)
+            };
+            let error = source_error(span, code, &description);
+            let error = match suggestions::did_you_mean(&name, candidates) {
+                Some(hint) => error.with_note("help", hint),
+                None => error,
+            };
+            if imported {
+                return Err(error);
+            }
+            context.warn_error(error);
+        }
+        (ast::StatementParameterId::GameMenuId(_), Some(_)) => {}
+        _ if imported => {
+            let description = format!("Unknown id '{}'.", name);
+            return Err(context.unknown_name(span, Code::UnknownId, &description, &name, prefix));
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn parse_id(param_id: Pair<Rule>) -> ast::StatementParameterId {
+    let rule = param_id.as_rule();
+    let text = param_id
+        .into_inner()
+        .next()
+        .expect("Failed to get inner id parameter.")
+        .as_str()
+        .to_string();
+
+    match rule {
+        Rule::animation_id => ast::StatementParameterId::AnimationId(text),
+        Rule::faction_id => ast::StatementParameterId::FactionId(text),
+        Rule::info_page_id => ast::StatementParameterId::InfoPageId(text),
+        Rule::item_id => ast::StatementParameterId::ItemId(text),
+        Rule::map_icon_id => ast::StatementParameterId::MapIconId(text),
+        Rule::game_menu_id => ast::StatementParameterId::GameMenuId(text),
+        Rule::mesh_id => ast::StatementParameterId::MeshId(text),
+        Rule::mission_template_id => ast::StatementParameterId::MissionTemplateId(text),
+        Rule::particle_system_id => ast::StatementParameterId::ParticleSystemId(text),
+        Rule::party_id => ast::StatementParameterId::PartyId(text),
+        Rule::party_template_id => ast::StatementParameterId::PartyTemplateId(text),
+        Rule::postfx_id => ast::StatementParameterId::PostfxId(text),
+        Rule::presentation_id => ast::StatementParameterId::PresentationId(text),
+        Rule::quest_id => ast::StatementParameterId::QuestId(text),
+        Rule::scene_prop_id => ast::StatementParameterId::ScenePropId(text),
+        Rule::scene_id => ast::StatementParameterId::SceneId(text),
+        Rule::script_id => ast::StatementParameterId::ScriptId(text),
+        Rule::skill_id => ast::StatementParameterId::SkillId(text),
+        Rule::sound_id => ast::StatementParameterId::SoundId(text),
+        Rule::string_id => ast::StatementParameterId::StringId(text),
+        Rule::tableau_material_id => ast::StatementParameterId::TableauMaterialId(text),
+        Rule::troop_id => ast::StatementParameterId::TroopId(text),
+        _ => unreachable!(),
+    }
+}
+
+// Gives the file as read, then as it's parsed, once preprocessed and with any statements that
+// have syntax errors blanked out, along with those errors.
+fn read_source(
+    path: &str,
+    rule: Rule,
+    options: &Options,
+) -> Result<(String, String, Vec<Error>), Error> {
+    let text = load_source(path)?;
+    let quoted = |error: Error| error.in_file(path).quoting(&text);
+    let source = preprocessor::preprocess(&text, &options.defines).map_err(quoted)?;
+    let (source, syntax_errors) =
+        recovery::recover(rule, source).map_err(|errors| quoted(Error::from_errors(errors)))?;
+    Ok((text, source, syntax_errors))
 }
 
 fn load_source(path: &str) -> Result<String, Error> {
     use std::fs;
-    fs::read_to_string(path).map_err(|_| Error::FailedPathLoad)
+    fs::read_to_string(path).map_err(|_| Error::FailedPathLoad(path.to_string()))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::{Mutex, PoisonError};
+
+    use cpython::{ObjectProtocol, PyDict, PyList, Python, PythonObject, ToPyObject};
+
+    use super::{find_patches, finish_project, parse, Error, Header, Options, Target};
+
+    // Stand-ins for what header_operations defines.
+    const OPERATIONS: &[(&str, i64)] = &[
+        ("call_script", 1),
+        ("try_end", 3),
+        ("try_begin", 4),
+        ("eq", 31),
+        ("position_rotate_z", 722),
+        ("position_get_x", 726),
+        ("position_get_y", 727),
+        ("position_set_x", 791),
+        ("position_set_y", 792),
+        ("display_message", 1106),
+        ("val_add", 2105),
+        ("val_sub", 2106),
+        ("assign", 2133),
+        ("str_store_string", 2320),
+        ("str_store_troop_name", 2321),
+    ];
+    const OPMASK_REGISTER: i64 = 1 << 56;
+
+    // The project is shared by everything parsed, so tests using it take turns.
+    static PROJECT: Mutex<()> = Mutex::new(());
+
+    // Runs the test with the GIL held, a fresh project and the module globals a build would have.
+    pub fn with_python<F: FnOnce(Python, &PyDict)>(test: F) {
+        let _turn = PROJECT.lock().unwrap_or_else(PoisonError::into_inner);
+        finish_project();
+
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let globals = PyDict::new(py);
+        for &(name, opcode) in OPERATIONS.iter() {
+            globals.set_item(py, name, opcode).unwrap();
+        }
+        for code in 0..64 {
+            globals.set_item(py, format!("reg{}", code), OPMASK_REGISTER | code).unwrap();
+        }
+        globals.set_item(py, "trp_player", 0).unwrap();
+        test(py, &globals);
+    }
+
+    pub fn options(warnings_as_errors: bool) -> Options {
+        let header = Header {
+            definitions: OPERATIONS.iter().map(|&(name, op)| (name.to_string(), op)).collect(),
+            lhs_operations: vec![2133],
+            global_lhs_operations: vec![],
+            can_fail_operations: vec![31],
+            source: String::new(),
+        };
+        let modules = ["header_common".to_string(), "header_operations".to_string()];
+        Options::new(vec![], Target::Warband, header, &modules, None, false, warnings_as_errors)
+    }
+
+    // Writes the source out to parse, named after the test so they don't trip over each other.
+    pub fn source_file(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mb_ext_script_{}.mbs", name));
+        std::fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // The script parsed from the source, as the tuple handed to the module system.
+    pub fn parse_script(py: Python, globals: &PyDict, name: &str, source: &str) -> String {
+        let path = source_file(name, source);
+        let script = parse(py, &path, name, globals, &options(false)).ok().unwrap();
+        let repr = script.to_py_object(py).into_object().repr(py).unwrap();
+        repr.to_string(py).unwrap().into_owned()
+    }
+
+    pub fn parse_error(py: Python, globals: &PyDict, name: &str, source: &str) -> Error {
+        let path = source_file(name, source);
+        parse(py, &path, name, globals, &options(false)).err().unwrap()
+    }
+
+    #[test]
+    pub fn unknown_operation_test() {
+        with_python(|py, globals| {
+            let error = parse_error(py, globals, "unknown_operation", "asign :x 1;\n");
+            let rendered = error.render(false);
+            assert!(rendered.starts_with("error: Unknown operation 'asign'.\n"));
+            assert!(rendered.ends_with("= help: Did you mean 'assign'?"));
+            assert_eq!(error.errors().len(), 1);
+
+            // Nothing close enough to suggest.
+            let error = parse_error(py, globals, "unknown_operation", "frobnicate;\n");
+            assert!(!error.render(false).contains("help"));
+        });
+    }
+
+    #[test]
+    pub fn unknown_identifier_test() {
+        with_python(|py, globals| {
+            let error = parse_error(py, globals, "unknown_identifier", "assign :x trp_playr;\n");
+            let rendered = error.render(false);
+            assert!(rendered.starts_with("error: Unknown identifier 'trp_playr'.\n"));
+            assert!(rendered.contains("1 | assign :x trp_playr;\n"));
+            assert!(rendered.ends_with("= help: Did you mean 'trp_player'?"));
+        });
+    }
+
+    #[test]
+    pub fn format_string_test() {
+        with_python(|py, globals| {
+            let source = "assign :gold 5;\n\
+                          display_message f\"{:gold} to {trp.player}, {str.3} and {{x}}\";\n\
+                          display_message f\"{str.greeting} {:gold}\";\n";
+            assert_eq!(
+                parse_script(py, globals, "format_string", source),
+                "('format_string', [(2133, ':gold', 5), \
+                 (2133, 72057594037927999, ':gold'), (2321, 63, 'trp_player'), \
+                 (1106, '@{reg63} to {s63}, {s3} and {x}'), \
+                 (2320, 63, 'str_greeting'), (2133, 72057594037927999, ':gold'), \
+                 (1106, '@{s63} {reg63}')])"
+            );
+        });
+    }
+
+    #[test]
+    pub fn coordinate_change_test() {
+        with_python(|py, globals| {
+            let source = "pos.1.x += 5;\n\
+                          pos.1.y -= :step;\n\
+                          pos.2.rot_z += 90;\n\
+                          pos.2.rot_z -= 45;\n";
+            assert_eq!(
+                parse_script(py, globals, "coordinate_change", source),
+                "('coordinate_change', [\
+                 (726, 72057594037927999, 1), (2105, 72057594037927999, 5), \
+                 (791, 1, 72057594037927999), \
+                 (727, 72057594037927999, 1), (2106, 72057594037927999, ':step'), \
+                 (792, 1, 72057594037927999), \
+                 (722, 2, 90), (722, 2, -45)])"
+            );
+
+            // Rotations can only be turned back by a number.
+            let source = "pos.2.rot_z -= :angle;\n";
+            assert_eq!(parse_error(py, globals, "coordinate_change", source).errors().len(), 1);
+        });
+    }
+
+    fn scripts(py: Python) -> PyList {
+        let scripts = "[('game_start', [(2133, ':a', 1)]), ('other', [(2133, ':b', 2)])]";
+        py.eval(scripts, None, None).unwrap().cast_into::<PyList>(py).unwrap()
+    }
+
+    fn repr(py: Python, scripts: &PyList) -> String {
+        let repr = scripts.as_object().repr(py).unwrap();
+        repr.to_string(py).unwrap().into_owned()
+    }
+
+    #[test]
+    pub fn patch_test() {
+        with_python(|py, globals| {
+            let source = "extend script game_start prepend { assign :c 3; }\n\
+                          extend script game_start append { assign :d 4; }\n\
+                          extend script other replace { assign :e 5; }\n";
+            let paths = vec![source_file("patch", source)];
+            let scripts = scripts(py);
+            let patches = find_patches(py, &scripts, &paths, globals, &options(false));
+            for (patch, index) in patches.ok().unwrap() {
+                patch.apply(py, &scripts, index).unwrap();
+            }
+            assert_eq!(
+                repr(py, &scripts),
+                "[('game_start', [(2133, ':c', 3), (2133, ':a', 1), (2133, ':d', 4)]), \
+                 ('other', [(2133, ':e', 5)])]"
+            );
+        });
+    }
+
+    #[test]
+    pub fn patch_not_found_test() {
+        with_python(|py, globals| {
+            let valid = source_file("patch_valid", "extend script other append { assign :c 3; }\n");
+            let source = "\nextend script game_end append { assign :d 4; }\n";
+            let missing = source_file("patch_missing", source);
+
+            // The valid file's patch isn't given back either, so nothing is applied.
+            let scripts = scripts(py);
+            let paths = vec![valid, missing.clone()];
+            let error = find_patches(py, &scripts, &paths, globals, &options(false)).err().unwrap();
+            assert_eq!(
+                error.render(false),
+                format!(
+                    "error: Script 'game_end' to extend was not found.\n \
+                     --> {}:2:1\n  \
+                     |\n\
+                     2 | extend script game_end append {{ assign :d 4; }}\n  \
+                     | ^^^^^^",
+                    missing
+                )
+            );
+        });
+    }
 }
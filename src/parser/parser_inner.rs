@@ -36,4 +36,133 @@ mod test {
     pub fn basic_test() {
         let _pairs = ParserInner::parse(Rule::main, BASIC_SRC).unwrap();
     }
+
+    const BLOCK_SRC: &str = r#"
+        let :i = 0;
+        try_for_range :i 0 10;
+            {
+                let :i = 0;
+                val_add :i 1;
+                { }
+            }
+        try_end;
+    "#;
+
+    #[test]
+    pub fn block_test() {
+        let _pairs = ParserInner::parse(Rule::main, BLOCK_SRC).unwrap();
+        assert!(ParserInner::parse(Rule::main, "{ let :i = 0; ").is_err());
+    }
+
+    #[test]
+    pub fn declaration_test() {
+        let src = r#"
+            param :lord: troop;
+            let :home: party = p.main_party;
+            let :n = 0;
+            alias :hero_name = str.3;
+            alias here = auto pos;
+        "#;
+        let _pairs = ParserInner::parse(Rule::main, src).unwrap();
+    }
+
+    #[test]
+    pub fn format_string_test() {
+        let src = r#"display_message f"{:gold} gold owed to { trp.player }" "@{reg0}";"#;
+        let _pairs = ParserInner::parse(Rule::main, src).unwrap();
+        assert!(ParserInner::parse(Rule::main, r#"display_message f"{:gold";"#).is_err());
+
+        let src = r#"display_message f"Hi {{playername}}, {:g}";"#;
+        let pairs = ParserInner::parse(Rule::main, src).unwrap();
+        let escapes = pairs.flatten().filter(|pair| pair.as_rule() == Rule::format_escape);
+        assert_eq!(escapes.count(), 2);
+    }
+
+    #[test]
+    pub fn position_field_test() {
+        let src = "pos.1.x = 100; :z = pos.2.z; pos.1.rot_z += 90; here.y -= 5; pos.3 = pos.1;";
+        let _pairs = ParserInner::parse(Rule::main, src).unwrap();
+        assert!(ParserInner::parse(Rule::main, "pos.1.w = 100;").is_err());
+    }
+
+    const TRIGGERS_SRC: &str = r#"
+        trigger every 24.0 delay 0 rearm 0 {
+            condition { eq g.cheat_mode 1; }
+            consequence { display_message "@Cheat mode is on."; }
+        }
+        trigger every ti_once { consequence { } }
+        trigger every 1 { }
+    "#;
+
+    #[test]
+    pub fn triggers_test() {
+        let _pairs = ParserInner::parse(Rule::triggers_file, TRIGGERS_SRC).unwrap();
+        let _pairs = ParserInner::parse(Rule::simple_triggers_file, "simple_trigger every 12 { }")
+            .unwrap();
+        assert!(ParserInner::parse(Rule::triggers_file, "simple_trigger every 12 { }").is_err());
+
+        let src = "mission_trigger ti_on_agent_spawn { } mission_trigger 1 delay 2 { }";
+        let _pairs = ParserInner::parse(Rule::mission_triggers_file, src).unwrap();
+
+        let src = "scene_prop_trigger ti_on_scene_prop_hit { store_trigger_param :instance 1; }";
+        let _pairs = ParserInner::parse(Rule::scene_prop_triggers_file, src).unwrap();
+        let src = "item_trigger ti_on_weapon_attack { play_sound snd.war_horn; }";
+        let _pairs = ParserInner::parse(Rule::item_triggers_file, src).unwrap();
+    }
+
+    const DIALOGS_SRC: &str = r#"
+        dialog anyone|plyr "start" -> "lord_talk" "@Greetings, {playername}." {
+            condition { eq g.talk_context 1; }
+            consequence { assign g.talked_to_lord 1; }
+        }
+        dialog trp.ramun_the_slave_trader "start" -> "close_window" "Hello." { }
+    "#;
+
+    #[test]
+    pub fn dialogs_test() {
+        let _pairs = ParserInner::parse(Rule::dialogs_file, DIALOGS_SRC).unwrap();
+        let src = r#"dialog anyone "start" "x" { }"#;
+        assert!(ParserInner::parse(Rule::dialogs_file, src).is_err());
+    }
+
+    const MENUS_SRC: &str = r#"
+        menu town_tavern flags mnf_disable_all_keys|0 "Tavern text" "none" {
+            on_enter { assign g.in_tavern 1; }
+            option go_back "Leave." {
+                condition { eq g.in_tavern 1; }
+                consequence { jump_to_menu mnu.town; }
+            }
+            option stay "Stay." { }
+        }
+        menu town "Town" "none" { }
+    "#;
+
+    #[test]
+    pub fn menus_test() {
+        let _pairs = ParserInner::parse(Rule::menus_file, MENUS_SRC).unwrap();
+    }
+
+    #[test]
+    pub fn presentations_test() {
+        let src = r#"
+            presentation my_overlay flags prsntf_manual_end_only mesh 0 {
+                on ti_on_presentation_load { set_fixed_point_multiplier 1000; }
+                on ti_on_presentation_event_state_change { }
+            }
+            presentation other mesh mesh.load_window { }
+        "#;
+        let _pairs = ParserInner::parse(Rule::presentations_file, src).unwrap();
+    }
+
+    #[test]
+    pub fn patches_test() {
+        let src = r#"
+            extend script game_start prepend { assign g.first 1; }
+            extend script game_start append { }
+            extend script game_get_total_wage replace { assign reg.0 0; }
+        "#;
+        let _pairs = ParserInner::parse(Rule::patches_file, src).unwrap();
+        let src = "extend script game_start insert { }";
+        assert!(ParserInner::parse(Rule::patches_file, src).is_err());
+    }
 }
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::parser::parser_inner::{ParserInner, Rule};
+use crate::parser::codes::Code;
+use crate::parser::{Error, SourceError};
+
+struct Conditional {
+    location: (usize, usize),
+    parent_active: bool,
+    taken: bool,
+    seen_else: bool,
+}
+
+// Blanks out lines excluded by #if / #else / #end rather than removing them so that
+// line numbers in later errors still match the original file.
+pub fn preprocess(source: &str, defines: &HashSet<String>) -> Result<String, Error> {
+    let mut output = String::with_capacity(source.len());
+    let mut stack: Vec<Conditional> = vec![];
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let active = stack.iter().all(|c| c.parent_active && c.taken != c.seen_else);
+        let trimmed = line.trim_start();
+
+        if !trimmed.starts_with('#') {
+            if active {
+                output.push_str(line);
+            }
+            else {
+                output.push_str(line_ending(line));
+            }
+            continue;
+        }
+
+        let location = (index + 1, line.len() - trimmed.len() + 1);
+        let directive = trimmed[1..].trim_end();
+        let (name, condition) = match directive.find(char::is_whitespace) {
+            Some(split) => (&directive[..split], directive[split..].trim()),
+            None => (directive, ""),
+        };
+
+        match name {
+            "if" => {
+                let taken = evaluate(condition, defines)
+                    .ok_or_else(|| error(location, "Invalid #if condition."))?;
+                stack.push(Conditional {
+                    location,
+                    parent_active: active,
+                    taken,
+                    seen_else: false,
+                });
+            }
+            "else" => match stack.last_mut() {
+                Some(conditional) if !conditional.seen_else => conditional.seen_else = true,
+                Some(_) => return Err(error(location, "#else already used for this #if.")),
+                None => return Err(error(location, "#else without a matching #if.")),
+            },
+            "end" => {
+                stack
+                    .pop()
+                    .ok_or_else(|| error(location, "#end without a matching #if."))?;
+            }
+            _ => return Err(error(location, &format!("Unknown directive '#{}'.", name))),
+        }
+
+        output.push_str(line_ending(line));
+    }
+
+    match stack.last() {
+        Some(conditional) => Err(error(conditional.location, "#if is missing its #end.")),
+        None => Ok(output),
+    }
+}
+
+fn line_ending(line: &str) -> &str {
+    if line.ends_with("\r\n") {
+        "\r\n"
+    }
+    else if line.ends_with('\n') {
+        "\n"
+    }
+    else {
+        ""
+    }
+}
+
+fn error(location: (usize, usize), description: &str) -> Error {
+    let description = description.to_string();
+    Error::SourceError(Box::new(SourceError::new(location, Code::Directive, description)))
+}
+
+fn evaluate(condition: &str, defines: &HashSet<String>) -> Option<bool> {
+    let mut pairs = ParserInner::parse(Rule::directive_condition, condition).ok()?;
+    Some(evaluate_pair(pairs.next()?, defines))
+}
+
+fn evaluate_pair(pair: Pair<Rule>, defines: &HashSet<String>) -> bool {
+    match pair.as_rule() {
+        Rule::directive_or => pair.into_inner().any(|p| evaluate_pair(p, defines)),
+        Rule::directive_and => pair.into_inner().all(|p| evaluate_pair(p, defines)),
+        Rule::directive_not => !evaluate_pair(
+            pair.into_inner().next().expect("Failed to get negated flag."),
+            defines,
+        ),
+        Rule::directive_flag => defines.contains(pair.as_str()),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::preprocess;
+    use std::collections::HashSet;
+
+    const SRC: &str = "a;\n#if wse && !debug\nb;\n#else\nc;\n#end\nd;\n";
+
+    #[test]
+    pub fn conditional_test() {
+        let defines: HashSet<String> = vec!["wse".to_string()].into_iter().collect();
+        assert_eq!(preprocess(SRC, &defines).ok().unwrap(), "a;\n\nb;\n\n\n\nd;\n");
+
+        let defines: HashSet<String> = vec!["wse".to_string(), "debug".to_string()]
+            .into_iter()
+            .collect();
+        assert_eq!(preprocess(SRC, &defines).ok().unwrap(), "a;\n\n\n\nc;\n\nd;\n");
+    }
+
+    #[test]
+    pub fn unbalanced_test() {
+        let defines = HashSet::new();
+        assert!(preprocess("#if wse\na;\n", &defines).is_err());
+        assert!(preprocess("#end\n", &defines).is_err());
+        assert!(preprocess("#if (wse || debug\n#end\n", &defines).is_err());
+    }
+}
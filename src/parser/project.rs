@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+use crate::parser::registers::RegisterKind;
+use crate::parser::tracking::{CallGraph, Globals};
+
+// What's shared by every .mbs file parsed in a build. Scripts from different files call each
+// other, so registers handed out in one file are checked against those used in another.
+#[derive(Default)]
+pub struct Project {
+    pub next_registers: HashMap<RegisterKind, u8>, // Where the next allocation starts
+    pub calls: CallGraph,
+    pub globals: Globals,
+}
+
+static PROJECT: LazyLock<Mutex<Project>> = LazyLock::new(Default::default);
+
+// Nothing is left half changed by a panic while it's held, so a poisoned lock is still usable.
+pub fn lock() -> MutexGuard<'static, Project> {
+    PROJECT.lock().unwrap_or_else(PoisonError::into_inner)
+}
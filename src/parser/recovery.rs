@@ -0,0 +1,82 @@
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+use pest::Parser;
+
+use crate::parser::codes::Code;
+use crate::parser::parser_inner::{ParserInner, Rule};
+use crate::parser::{Error, SourceError};
+
+// Statements that fail to parse are blanked out up to the next `;` so the rest of the file can
+// still be checked, keeping line breaks so later locations don't move. Gives up with the syntax
+// errors found so far when there's no `;` left or blanking would cut through a block's braces.
+pub fn recover(rule: Rule, mut source: String) -> Result<(String, Vec<Error>), Vec<Error>> {
+    let mut errors = vec![];
+
+    loop {
+        let error = match ParserInner::parse(rule, &source) {
+            Ok(_) => return Ok((source, errors)),
+            Err(error) => error,
+        };
+        let position = match error.location {
+            InputLocation::Pos(position) | InputLocation::Span((position, _)) => position,
+        };
+        errors.push(syntax_error(error));
+
+        let start = source[..position]
+            .rfind(|c| ";{}".contains(c))
+            .map_or(0, |index| index + 1);
+        let end = match source[position..].find(';') {
+            Some(index) => position + index + 1,
+            None => return Err(errors),
+        };
+        if source[start..end].contains(['{', '}']) {
+            return Err(errors);
+        }
+
+        let blank: String = source[start..end]
+            .chars()
+            .map(|c| if c == '\n' || c == '\r' { c } else { ' ' })
+            .collect();
+        source.replace_range(start..end, &blank);
+    }
+}
+
+pub fn syntax_error(error: pest::error::Error<Rule>) -> Error {
+    let (location, end) = match error.line_col {
+        LineColLocation::Pos(location) => (location, None),
+        LineColLocation::Span(location, end) => (location, Some(end)),
+    };
+    let description = match error.variant {
+        ErrorVariant::ParsingError { ref positives, .. } if !positives.is_empty() => {
+            let rules: Vec<String> = positives
+                .iter()
+                .map(|rule| format!("{:?}", rule).replace('_', " "))
+                .collect();
+            format!("Syntax error, expected {}.", rules.join(" or "))
+        }
+        ErrorVariant::ParsingError { .. } => "Syntax error.".to_string(),
+        ErrorVariant::CustomError { message } => message,
+    };
+
+    Error::SourceError(Box::new(SourceError {
+        end,
+        ..SourceError::new(location, Code::Syntax, description)
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::recover;
+    use crate::parser::parser_inner::Rule;
+
+    #[test]
+    pub fn recover_test() {
+        let source = "assign :x 1;\nassign ?? 2;\nassign :y 3;\nfoo @\n  bar;\n".to_string();
+        let (source, errors) = recover(Rule::main, source).ok().unwrap();
+        assert_eq!(source, "assign :x 1;\n            \nassign :y 3;\n     \n      \n");
+        assert_eq!(errors.len(), 2);
+
+        // The statement can't be skipped without losing the end of its block.
+        let source = "{ assign :x 1 }\nassign :y 2;\n".to_string();
+        assert_eq!(recover(Rule::main, source).err().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::parser::targets::Target;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum RegisterKind {
+    Register,
+    String,
+    Position,
+}
+
+impl RegisterKind {
+    pub fn from_prefix(prefix: &str) -> Option<RegisterKind> {
+        match prefix {
+            "reg" => Some(RegisterKind::Register),
+            "str" => Some(RegisterKind::String),
+            "pos" => Some(RegisterKind::Position),
+            _ => None,
+        }
+    }
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            RegisterKind::Register => "reg",
+            RegisterKind::String => "str",
+            RegisterKind::Position => "pos",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Register(pub RegisterKind, pub u8);
+
+// Filled in by the engine before triggers such as ti_on_agent_hit run, with the weapon used and
+// the position of the blow.
+pub const ENGINE_REGISTERS: &[Register] = &[
+    Register(RegisterKind::Register, 0),
+    Register(RegisterKind::Position, 0),
+];
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.0.prefix(), self.1)
+    }
+}
+
+// Reads a register as written, e.g. `reg.12`, holding it to the range the target has. Gives the
+// description of the error otherwise.
+pub fn parse_register(kind: RegisterKind, text: &str, target: Target) -> Result<Register, String> {
+    let count = target.register_count();
+    match text[kind.prefix().len() + 1..].parse::<u8>() {
+        Ok(code) if code < count => Ok(Register(kind, code)),
+        _ => Err(format!(
+            "Register {} is out of range, {} has {}.0 to {}.{}.",
+            text,
+            target,
+            kind.prefix(),
+            kind.prefix(),
+            count - 1
+        )),
+    }
+}
+
+// Hands out the registers for `auto` aliases and format strings of one file, keeping clear of
+// those the file writes out itself. Each file gets its own so they're freed once it's parsed,
+// calls between files that still end up sharing one are found by `CallGraph`.
+#[derive(Default)]
+pub struct Allocator {
+    used: HashSet<Register>,
+}
+
+impl Allocator {
+    pub fn reserve(&mut self, register: Register) {
+        self.used.insert(register);
+    }
+
+    // Takes the first free register of the `count` of the kind at or below `start`, wrapping
+    // round to the top. Files take turns starting further down, so those calling each other are
+    // unlikely to be given the same. The engine's own registers are never handed out.
+    pub fn allocate(&mut self, kind: RegisterKind, count: u8, start: u8) -> Option<Register> {
+        let count = u16::from(count);
+        let register = (0..count)
+            .map(|offset| Register(kind, ((u16::from(start) + count - offset) % count) as u8))
+            .find(|register| {
+                !self.used.contains(register) && !ENGINE_REGISTERS.contains(register)
+            })?;
+        self.used.insert(register);
+        Some(register)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_register, Allocator, Register, RegisterKind};
+    use crate::parser::targets::Target;
+
+    #[test]
+    pub fn allocate_test() {
+        let mut allocator = Allocator::default();
+        allocator.reserve(Register(RegisterKind::String, 63));
+        assert_eq!(
+            allocator.allocate(RegisterKind::String, 64, 63),
+            Some(Register(RegisterKind::String, 62))
+        );
+        assert_eq!(
+            allocator.allocate(RegisterKind::Position, 64, 40),
+            Some(Register(RegisterKind::Position, 40))
+        );
+
+        // Wraps round past the engine's register 0.
+        allocator.reserve(Register(RegisterKind::Register, 1));
+        assert_eq!(
+            allocator.allocate(RegisterKind::Register, 64, 1),
+            Some(Register(RegisterKind::Register, 63))
+        );
+        assert_eq!(allocator.allocate(RegisterKind::Register, 2, 1), None);
+    }
+
+    #[test]
+    pub fn range_test() {
+        assert_eq!(
+            parse_register(RegisterKind::String, "str.63", Target::Warband),
+            Ok(Register(RegisterKind::String, 63))
+        );
+        assert_eq!(
+            parse_register(RegisterKind::Register, "reg.64", Target::Warband),
+            Err("Register reg.64 is out of range, warband has reg.0 to reg.63.".to_string())
+        );
+        assert_eq!(
+            parse_register(RegisterKind::Position, "pos.127", Target::Wse),
+            Ok(Register(RegisterKind::Position, 127))
+        );
+        assert_eq!(
+            parse_register(RegisterKind::Register, "reg.300", Target::Wse),
+            Err("Register reg.300 is out of range, wse has reg.0 to reg.127.".to_string())
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet};
+
+pub enum ScopeError {
+    AlreadyDeclared,
+    OutOfScope,
+}
+
+// Tracks locals declared with `let` across nested `{}` blocks. Locals that are
+// never declared are passed through untouched so existing scripts keep working.
+pub struct Scopes {
+    frames: Vec<HashMap<String, String>>,
+    free: HashSet<String>,
+    retired: HashSet<String>,
+    renames: HashMap<String, usize>,
+    sources: HashMap<String, String>, // Source names of the renamed locals, by emitted name
+}
+
+impl Scopes {
+    pub fn new() -> Self {
+        Scopes {
+            frames: vec![HashMap::new()],
+            free: HashSet::new(),
+            retired: HashSet::new(),
+            renames: HashMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    pub fn pop(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            // A name is only out of scope once nothing outside the block binds it, uses after
+            // the block go back to an outer declaration or to the free local it shadowed.
+            for name in frame.into_keys() {
+                if self.lookup(&name).is_none() && !self.free.contains(&name) {
+                    self.retired.insert(name);
+                }
+            }
+        }
+    }
+
+    pub fn declare(&mut self, name: &str) -> Result<String, ScopeError> {
+        let shadows = self.lookup(name).is_some() || self.free.contains(name);
+        let frame = self.frames.last_mut().expect("Scope stack is empty.");
+
+        if frame.contains_key(name) {
+            return Err(ScopeError::AlreadyDeclared);
+        }
+
+        // Identifiers in mbs can't contain digits, so a numbered suffix can
+        // never collide with a name written by hand.
+        let emitted = if shadows {
+            let count = self.renames.entry(name.to_string()).or_insert(0);
+            *count += 1;
+            let emitted = format!("{}_{}", name, count);
+            self.sources.insert(emitted.clone(), name.to_string());
+            emitted
+        }
+        else {
+            name.to_string()
+        };
+
+        frame.insert(name.to_string(), emitted.clone());
+        self.retired.remove(name);
+        Ok(emitted)
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Result<String, ScopeError> {
+        if let Some(emitted) = self.lookup(name) {
+            return Ok(emitted.clone());
+        }
+
+        if self.retired.contains(name) {
+            return Err(ScopeError::OutOfScope);
+        }
+
+        self.free.insert(name.to_string());
+        Ok(name.to_string())
+    }
+
+    // The name a local was written as, given the name it's emitted as.
+    pub fn source<'b>(&'b self, emitted: &'b str) -> &'b str {
+        self.sources.get(emitted).map_or(emitted, String::as_str)
+    }
+
+    fn lookup(&self, name: &str) -> Option<&String> {
+        self.frames.iter().rev().find_map(|frame| frame.get(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Scopes;
+
+    #[test]
+    pub fn shadowing_test() {
+        let mut scopes = Scopes::new();
+        assert_eq!(scopes.declare("i").ok(), Some("i".to_string()));
+        scopes.push();
+        assert_eq!(scopes.declare("i").ok(), Some("i_1".to_string()));
+        assert_eq!(scopes.resolve("i").ok(), Some("i_1".to_string()));
+        assert_eq!(scopes.source("i_1"), "i");
+        scopes.pop();
+        assert_eq!(scopes.resolve("i").ok(), Some("i".to_string()));
+    }
+
+    #[test]
+    pub fn out_of_scope_test() {
+        let mut scopes = Scopes::new();
+        scopes.push();
+        assert!(scopes.declare("j").is_ok());
+        scopes.pop();
+        assert!(scopes.resolve("j").is_err());
+        assert!(scopes.resolve("k").is_ok());
+        assert!(scopes.declare("k").is_ok());
+    }
+
+    #[test]
+    pub fn shadowed_free_test() {
+        let mut scopes = Scopes::new();
+        assert_eq!(scopes.resolve("j").ok(), Some("j".to_string()));
+        scopes.push();
+        assert_eq!(scopes.declare("j").ok(), Some("j_1".to_string()));
+        scopes.pop();
+        assert_eq!(scopes.resolve("j").ok(), Some("j".to_string()));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::parser::ast::StatementParameter;
+use crate::parser::types::Type;
+
+// Signatures we maintain ourselves, taking precedence over header_operations' comments. A mod
+// can keep its own file of them too, which takes precedence over both.
+const OVERRIDES: &str = include_str!("signatures.txt");
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OperandKind {
+    Destination,
+    StringRegister,
+    PositionRegister,
+    String,
+    Id(Type),
+    Value,
+}
+
+impl OperandKind {
+    // Named after the placeholders header_operations uses, e.g. <destination> or <troop_id>.
+    fn from_placeholder(placeholder: &str) -> OperandKind {
+        match placeholder {
+            _ if placeholder.starts_with("destination") => OperandKind::Destination,
+            "string_register" | "string_no" | "string_reg" => OperandKind::StringRegister,
+            "position" | "position_no" | "position_register" | "position_target"
+            | "position_source" | "pos" => OperandKind::PositionRegister,
+            "string_id" | "string" => OperandKind::String,
+            _ => placeholder
+                .strip_suffix("_id")
+                .and_then(Type::from_name)
+                .map_or(OperandKind::Value, OperandKind::Id),
+        }
+    }
+
+    // `declared` is the type a local was declared with, which is all that's known about its value.
+    pub fn accepts(self, parameter: &StatementParameter, declared: Option<Type>) -> bool {
+        use crate::parser::ast::StatementParameter as P;
+
+        if let (P::LocalVariable(_), Some(declared)) = (parameter, declared) {
+            return match self {
+                OperandKind::StringRegister => declared == Type::String,
+                OperandKind::PositionRegister => declared == Type::Position,
+                OperandKind::Id(expected) => declared == expected,
+                _ => true,
+            };
+        }
+
+        let is_variable = matches!(
+            parameter,
+            P::LocalVariable(_) | P::GlobalVariable(_) | P::AutoPrefixedGlobalVariable(_)
+        );
+        let is_plain_value = is_variable
+            || matches!(parameter, P::Register(..) | P::Number(_) | P::Identifier(_));
+
+        match self {
+            OperandKind::Destination => is_variable || matches!(parameter, P::Register(..)),
+            OperandKind::StringRegister => {
+                is_plain_value || matches!(parameter, P::StringRegister(_))
+            }
+            OperandKind::PositionRegister => {
+                is_plain_value || matches!(parameter, P::PositionRegister(_))
+            }
+            OperandKind::String => match parameter {
+                P::String(_) => true,
+                P::StatementParamId(id) => id.module() == "ID_strings",
+                _ => is_plain_value,
+            },
+            OperandKind::Id(expected) => match parameter {
+                P::StatementParamId(id) => Type::of_id(id) == Some(expected),
+                _ => is_plain_value,
+            },
+            OperandKind::Value => true,
+        }
+    }
+}
+
+impl fmt::Display for OperandKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OperandKind::Destination => write!(f, "a variable or register to store into"),
+            OperandKind::StringRegister => write!(f, "a string register"),
+            OperandKind::PositionRegister => write!(f, "a position register"),
+            OperandKind::String => write!(f, "a string"),
+            OperandKind::Id(expected) => write!(f, "a {} id", expected),
+            OperandKind::Value => write!(f, "a value"),
+        }
+    }
+}
+
+pub fn describe(parameter: &StatementParameter) -> String {
+    use crate::parser::ast::StatementParameter as P;
+
+    match parameter {
+        P::Identifier(_) => "a constant".to_string(),
+        P::Register(..) => "a register".to_string(),
+        P::StringRegister(_) => "a string register".to_string(),
+        P::PositionRegister(_) => "a position register".to_string(),
+        P::LocalVariable(_) => "a local variable".to_string(),
+        P::GlobalVariable(_) | P::AutoPrefixedGlobalVariable(_) => {
+            "a global variable".to_string()
+        }
+        P::StatementParamId(id) => format!("the id '{}'", id.name()),
+        P::Number(_) => "a number".to_string(),
+        P::String(_) => "a string".to_string(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    pub operands: Vec<OperandKind>,
+    pub required: usize,
+    pub variadic: bool,
+}
+
+impl Signature {
+    pub fn accepts_count(&self, count: usize) -> bool {
+        count >= self.required && (self.variadic || count <= self.operands.len())
+    }
+
+    pub fn describe_count(&self) -> String {
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        let max = self.operands.len();
+
+        if self.variadic {
+            format!("at least {} operand{}", self.required, plural(self.required))
+        }
+        else if self.required == max {
+            format!("{} operand{}", max, plural(max))
+        }
+        else {
+            format!("{} to {} operands", self.required, max)
+        }
+    }
+
+    // Extra operands of a variadic operation take the kind of the last one listed.
+    pub fn operand(&self, index: usize) -> Option<OperandKind> {
+        match self.operands.get(index) {
+            Some(kind) => Some(*kind),
+            None if self.variadic => self.operands.last().cloned(),
+            None => None,
+        }
+    }
+}
+
+pub struct Signatures {
+    signatures: HashMap<String, Signature>,
+}
+
+impl Signatures {
+    pub fn new(header_source: &str, overrides: Option<&str>) -> Self {
+        let mut signatures = parse_header(header_source);
+        signatures.extend(parse_overrides(OVERRIDES));
+        signatures.extend(overrides.map(parse_overrides).unwrap_or_default());
+        Signatures { signatures }
+    }
+
+    pub fn get(&self, operation: &str) -> Option<&Signature> {
+        self.signatures.get(operation)
+    }
+}
+
+// Reads the `(operation, <operand>, [<optional>], [<repeated>...])` usage notes written in
+// header_operations' comments, skipping notes that don't belong to the operation on their line.
+fn parse_header(source: &str) -> HashMap<String, Signature> {
+    source
+        .lines()
+        .filter_map(|line| line.split_once('#'))
+        .filter_map(|(code, comment)| {
+            let (name, signature) = parse_usage(comment)?;
+            match code.split_once('=') {
+                Some((defined, _)) if defined.trim() != name => None,
+                _ => Some((name, signature)),
+            }
+        })
+        .collect()
+}
+
+// The override file holds one usage per line in the same form, with # starting a comment line.
+fn parse_overrides(source: &str) -> HashMap<String, Signature> {
+    source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(parse_usage)
+        .collect()
+}
+
+fn parse_usage(comment: &str) -> Option<(String, Signature)> {
+    let usage = comment.trim().strip_prefix('(')?;
+    let usage = &usage[..usage.find(')')?];
+    let mut parts = usage.split(',').map(str::trim).filter(|part| !part.is_empty());
+    let name = parts.next()?;
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    let mut signature = Signature {
+        operands: vec![],
+        required: 0,
+        variadic: false,
+    };
+    for part in parts {
+        let optional = part.starts_with('[');
+        signature.variadic |= part.contains("...");
+        let placeholder = part.trim_matches(|c| "[]<>. ".contains(c));
+        signature.operands.push(OperandKind::from_placeholder(placeholder));
+        if !optional && !signature.variadic {
+            signature.required += 1;
+        }
+    }
+
+    Some((name.to_string(), signature))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_header, parse_overrides, OperandKind, Signature, Signatures};
+    use crate::parser::ast::StatementParameter;
+    use crate::parser::types::Type;
+
+    const HEADER_SRC: &str = r#"
+party_get_slot = 520 # (party_get_slot, <destination>, <party_id>, <slot_no>),
+display_message = 1106 # (display_message, <string_id>, [<hex_colour_code>]),
+call_script = 1 # (call_script, <script_id>, [<script_param>...]),
+try_end = 3 # (try_end),
+neg = 0x80000000 # (neg|<operation>) negates the operation
+this_or_next = 0x40000000 # (this_or_next|eq, <value>, <value>)
+"#;
+
+    #[test]
+    pub fn parse_signatures_test() {
+        let signatures = parse_header(HEADER_SRC);
+        assert_eq!(
+            signatures["party_get_slot"],
+            Signature {
+                operands: vec![
+                    OperandKind::Destination,
+                    OperandKind::Id(Type::Party),
+                    OperandKind::Value
+                ],
+                required: 3,
+                variadic: false,
+            }
+        );
+        assert!(signatures["display_message"].accepts_count(1));
+        assert!(!signatures["display_message"].accepts_count(3));
+        assert!(signatures["call_script"].accepts_count(5));
+        assert_eq!(signatures["call_script"].describe_count(), "at least 1 operand");
+        assert!(signatures["try_end"].accepts_count(0));
+        assert!(!signatures.contains_key("neg"));
+        assert!(!signatures.contains_key("this_or_next|eq"));
+
+        let overrides = parse_overrides("# A comment\n(troop_set_slot, <troop_id>, <value>)\n");
+        assert_eq!(overrides["troop_set_slot"].operand(0), Some(OperandKind::Id(Type::Troop)));
+
+        let signatures = Signatures::new(HEADER_SRC, Some("(try_end, <value>)"));
+        assert!(!signatures.get("try_end").unwrap().accepts_count(0));
+    }
+
+    #[test]
+    pub fn accepts_test() {
+        let local = StatementParameter::LocalVariable("lord".to_string());
+        let party = OperandKind::Id(Type::Party);
+        assert!(party.accepts(&local, None));
+        assert!(party.accepts(&local, Some(Type::Party)));
+        assert!(!party.accepts(&local, Some(Type::Troop)));
+        assert!(!OperandKind::PositionRegister.accepts(&local, Some(Type::Troop)));
+        assert!(OperandKind::Value.accepts(&local, Some(Type::Troop)));
+        assert!(!party.accepts(&StatementParameter::String("@".to_string()), None));
+    }
+}
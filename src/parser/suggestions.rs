@@ -0,0 +1,69 @@
+const MAX_SUGGESTIONS: usize = 3;
+
+// Formats the closest candidates as a hint to go with an error, if anything is close.
+pub fn did_you_mean<I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Allow roughly one mistake for every three characters typed.
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut matches: Vec<(usize, String)> = candidates
+        .into_iter()
+        .filter(|candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    matches.sort();
+    matches.dedup();
+
+    let names: Vec<String> = matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| format!("'{}'", candidate))
+        .collect();
+
+    match names.split_last() {
+        None => None,
+        Some((last, [])) => Some(format!("Did you mean {}?", last)),
+        Some((last, rest)) => Some(format!("Did you mean {} or {}?", rest.join(", "), last)),
+    }
+}
+
+// Levenshtein distance, keeping a single row of the table.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + if a_char == *b_char { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{did_you_mean, edit_distance};
+
+    #[test]
+    pub fn did_you_mean_test() {
+        assert_eq!(edit_distance("asign", "assign"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let candidates = ["assign", "align", "val_add", "trp_player"];
+        let candidates = || candidates.iter().map(|c| c.to_string());
+        assert_eq!(
+            did_you_mean("asign", candidates()).unwrap(),
+            "Did you mean 'align' or 'assign'?"
+        );
+        assert_eq!(did_you_mean("trp_playr", candidates()).unwrap(), "Did you mean 'trp_player'?");
+        assert_eq!(did_you_mean("store_random", candidates()), None);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Operations we list ourselves under the target that added them, since neither the opcodes nor
+// header_operations say which engine has what.
+const ADDED_OPERATIONS: &str = include_str!("operations.txt");
+
+// Flags OR'd into opcodes rather than operations themselves.
+const OPERATION_FLAGS: &[&str] = &["neg", "this_or_next"];
+
+// In the order they build on each other, each having every operation of those before it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Target {
+    MountAndBlade,
+    Warband,
+    Wse,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Target> {
+        Some(match name {
+            "mb1011" => Target::MountAndBlade,
+            "warband" | "vanilla" => Target::Warband,
+            "wse" | "wse2" => Target::Wse,
+            _ => return None,
+        })
+    }
+
+    // Of each of reg, str and pos.
+    pub fn register_count(self) -> u8 {
+        match self {
+            Target::Wse => 128,
+            Target::Warband | Target::MountAndBlade => 64,
+        }
+    }
+}
+
+// Each listed operation or `*` prefix, with the target that added it.
+fn added_operations() -> Vec<(Target, &'static str)> {
+    let mut added = vec![];
+    let mut target = None;
+    for line in ADDED_OPERATIONS.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            Some(name) => target = Target::from_name(name),
+            None => added.extend(target.map(|target| (target, line))),
+        }
+    }
+    added
+}
+
+fn added_by(name: &str, added: &[(Target, &str)]) -> Option<Target> {
+    added
+        .iter()
+        .find(|(_, listed)| match listed.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *listed,
+        })
+        .map(|&(target, _)| target)
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Target::MountAndBlade => "mb1011",
+            Target::Warband => "warband",
+            Target::Wse => "wse",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// What's read from the imported header_operations module.
+pub struct Header {
+    pub definitions: Vec<(String, i64)>, // Every integer it defines, keyed by name
+    pub lhs_operations: Vec<i64>,
+    pub global_lhs_operations: Vec<i64>,
+    pub can_fail_operations: Vec<i64>,
+    pub source: String,
+}
+
+pub struct Profile {
+    pub target: Target,
+    operations: HashMap<String, i64>,
+    unavailable: HashSet<String>, // Those header_operations defines that the target lacks
+    lhs_operations: HashSet<i64>,
+    global_lhs_operations: HashSet<i64>,
+    can_fail_operations: HashSet<i64>,
+}
+
+impl Profile {
+    pub fn new(target: Target, header: &Header) -> Self {
+        let added = added_operations();
+        let (operations, unavailable): (Vec<_>, Vec<_>) = header
+            .definitions
+            .iter()
+            .filter(|(name, _)| !name.starts_with('_') && !OPERATION_FLAGS.contains(&&name[..]))
+            .cloned()
+            .partition(|(name, _)| added_by(name, &added).is_none_or(|added| added <= target));
+
+        Profile {
+            target,
+            operations: operations.into_iter().collect(),
+            unavailable: unavailable.into_iter().map(|(name, _)| name).collect(),
+            lhs_operations: header.lhs_operations.iter().cloned().collect(),
+            global_lhs_operations: header.global_lhs_operations.iter().cloned().collect(),
+            can_fail_operations: header.can_fail_operations.iter().cloned().collect(),
+        }
+    }
+
+    pub fn has_operation(&self, name: &str) -> bool {
+        self.operations.contains_key(name)
+    }
+
+    pub fn operation_names(&self) -> Vec<String> {
+        self.operations.keys().cloned().collect()
+    }
+
+    // Whether header_operations defines the operation but the target doesn't have it.
+    pub fn lacks_operation(&self, name: &str) -> bool {
+        self.unavailable.contains(name)
+    }
+
+    // Variable usage is only checked when header_operations lists the assigning operations.
+    pub fn tracks_assignments(&self) -> bool {
+        !self.lhs_operations.is_empty()
+    }
+
+    // Whether the operation stores into its first operand when it's a local.
+    pub fn assigns_local(&self, name: &str) -> bool {
+        self.operations
+            .get(name)
+            .is_some_and(|opcode| self.lhs_operations.contains(opcode))
+    }
+
+    // Whether the operation stores into its first operand when it's a global.
+    pub fn assigns_global(&self, name: &str) -> bool {
+        self.operations.get(name).is_some_and(|opcode| {
+            self.lhs_operations.contains(opcode) || self.global_lhs_operations.contains(opcode)
+        })
+    }
+
+    // Likewise failures are only checked when header_operations lists the failable operations.
+    pub fn tracks_failures(&self) -> bool {
+        !self.can_fail_operations.is_empty()
+    }
+
+    pub fn can_fail(&self, name: &str) -> bool {
+        self.operations
+            .get(name)
+            .is_some_and(|opcode| self.can_fail_operations.contains(opcode))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Header, Profile, Target};
+
+    #[test]
+    pub fn profile_test() {
+        let header = Header {
+            definitions: vec![
+                ("assign".to_string(), 2133),
+                ("dict_create".to_string(), 3200),
+                ("break_loop".to_string(), 8),
+                ("multiplayer_is_server".to_string(), 417),
+                ("neg".to_string(), 0x8000_0000),
+            ],
+            lhs_operations: vec![2133],
+            global_lhs_operations: vec![],
+            can_fail_operations: vec![],
+            source: String::new(),
+        };
+        let profile = Profile::new(Target::from_name("vanilla").unwrap(), &header);
+        assert!(profile.has_operation("assign"));
+        assert!(profile.has_operation("multiplayer_is_server"));
+        assert!(!profile.has_operation("dict_create"));
+        assert!(profile.lacks_operation("break_loop"));
+        assert!(!profile.has_operation("neg"));
+        assert!(!profile.lacks_operation("neg"));
+        assert!(!profile.lacks_operation("slot_troop_x"));
+        assert!(profile.assigns_local("assign"));
+
+        let profile = Profile::new(Target::from_name("mb1011").unwrap(), &header);
+        assert!(profile.has_operation("assign"));
+        assert!(profile.lacks_operation("multiplayer_is_server"));
+        assert!(profile.lacks_operation("dict_create"));
+        assert_eq!(Target::MountAndBlade.register_count(), 64);
+
+        let profile = Profile::new(Target::Wse, &header);
+        assert!(profile.has_operation("dict_create"));
+        assert!(profile.has_operation("break_loop"));
+        assert!(!profile.assigns_global("dict_create"));
+        assert_eq!(Target::Wse.register_count(), 128);
+    }
+}
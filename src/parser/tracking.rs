@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::registers::Register;
+
+// Which locals of a block have been assigned and read. Locals are keyed by their emitted name,
+// but are reported by the name written in the source.
+#[derive(Default)]
+pub struct Locals {
+    usages: HashMap<String, LocalUsage>,
+}
+
+// A local's source name and where it's misused.
+pub type Misuse = (String, (usize, usize));
+
+struct LocalUsage {
+    name: String,
+    assigned: Option<(usize, usize)>,
+    read: bool,
+    read_unassigned: Option<(usize, usize)>, // Where it's first read before being assigned
+}
+
+impl Locals {
+    pub fn assign(&mut self, emitted: &str, name: &str, location: (usize, usize)) {
+        self.usage(emitted, name).assigned.get_or_insert(location);
+    }
+
+    pub fn read(&mut self, emitted: &str, name: &str, location: (usize, usize)) {
+        let usage = self.usage(emitted, name);
+        if usage.assigned.is_none() && !usage.read {
+            usage.read_unassigned = Some(location);
+        }
+        usage.read = true;
+    }
+
+    // Ends the block, giving the locals that were assigned and never read, then those read before
+    // being assigned, each in source order.
+    pub fn take_misused(&mut self) -> (Vec<Misuse>, Vec<Misuse>) {
+        let mut unused = vec![];
+        let mut unassigned = vec![];
+        for (_, usage) in self.usages.drain() {
+            match usage.assigned {
+                Some(location) if !usage.read => unused.push((usage.name.clone(), location)),
+                _ => {}
+            }
+            if let Some(location) = usage.read_unassigned {
+                unassigned.push((usage.name, location));
+            }
+        }
+        unused.sort_by_key(|&(_, location)| location);
+        unassigned.sort_by_key(|&(_, location)| location);
+        (unused, unassigned)
+    }
+
+    fn usage(&mut self, emitted: &str, name: &str) -> &mut LocalUsage {
+        self.usages.entry(emitted.to_string()).or_insert_with(|| LocalUsage {
+            name: name.to_string(),
+            assigned: None,
+            read: false,
+            read_unassigned: None,
+        })
+    }
+}
+
+// Globals are shared by every file of a project, so they're tallied across all of them.
+#[derive(Default)]
+pub struct Globals {
+    usages: HashMap<String, GlobalUsage>,
+}
+
+pub struct GlobalUsage {
+    pub path: String,
+    pub location: (usize, usize), // Of the first reference
+    pub line: String,             // Likewise, as it was parsed
+    pub assigned: bool,
+    references: usize,
+}
+
+impl Globals {
+    // `line` is that of the reference, quoted if the global turns out to be used only once.
+    pub fn record(
+        &mut self,
+        name: String,
+        path: &str,
+        location: (usize, usize),
+        line: &str,
+        assigned: bool,
+    ) {
+        let usage = self.usages.entry(name).or_insert_with(|| GlobalUsage {
+            path: path.to_string(),
+            location,
+            line: line.to_string(),
+            assigned: false,
+            references: 0,
+        });
+        usage.references += 1;
+        usage.assigned |= assigned;
+    }
+
+    // The globals referenced only once, in the order they appear.
+    pub fn take_single_use(&mut self) -> Vec<(String, GlobalUsage)> {
+        let mut single_use: Vec<(String, GlobalUsage)> = self
+            .usages
+            .drain()
+            .filter(|(_, usage)| usage.references == 1)
+            .collect();
+        single_use.sort_by(|(_, a), (_, b)| (&a.path, a.location).cmp(&(&b.path, b.location)));
+        single_use
+    }
+}
+
+// The registers each script uses and the scripts it calls, across every file, so calls made while
+// an `auto` alias holds a register can be checked against what they might overwrite.
+#[derive(Default)]
+pub struct CallGraph {
+    scripts: HashMap<String, ScriptUsage>,
+    calls: Vec<Call>,
+}
+
+#[derive(Default)]
+struct ScriptUsage {
+    registers: HashSet<Register>,
+    calls: HashSet<String>,
+}
+
+pub struct Call {
+    pub path: String,
+    pub location: (usize, usize),
+    pub line: String,       // As it was parsed
+    pub script: String,     // The one called
+    pub held: Vec<Register>, // By `auto` aliases in the calling file
+}
+
+impl CallGraph {
+    pub fn use_register(&mut self, script: &str, register: Register) {
+        self.usage(script).registers.insert(register);
+    }
+
+    pub fn call(&mut self, caller: Option<&str>, call: Call) {
+        if let Some(caller) = caller {
+            self.usage(caller).calls.insert(call.script.clone());
+        }
+        if !call.held.is_empty() {
+            self.calls.push(call);
+        }
+    }
+
+    // Each call along with the registers held across it that the script called, or any script it
+    // goes on to call, also uses.
+    pub fn take_clashes(&mut self) -> Vec<(Call, Vec<Register>)> {
+        let mut clashes = vec![];
+        for call in self.calls.drain(..).collect::<Vec<Call>>() {
+            let used = self.registers_reached(&call.script);
+            let mut clashing: Vec<Register> =
+                call.held.iter().filter(|register| used.contains(register)).cloned().collect();
+            if !clashing.is_empty() {
+                clashing.sort();
+                clashes.push((call, clashing));
+            }
+        }
+        self.scripts.clear();
+        clashes
+    }
+
+    fn registers_reached(&self, script: &str) -> HashSet<Register> {
+        let mut registers = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![script];
+        while let Some(script) = pending.pop() {
+            if !visited.insert(script) {
+                continue;
+            }
+            if let Some(usage) = self.scripts.get(script) {
+                registers.extend(usage.registers.iter().cloned());
+                pending.extend(usage.calls.iter().map(String::as_str));
+            }
+        }
+        registers
+    }
+
+    fn usage(&mut self, script: &str) -> &mut ScriptUsage {
+        self.scripts.entry(script.to_string()).or_default()
+    }
+}
+
+// The try blocks open at each point of a block, innermost last, and whether anything outside of
+// them can fail.
+#[derive(Default)]
+pub struct TryBlocks {
+    open: Vec<(String, (usize, usize))>,
+    fails_outside: bool,
+}
+
+impl TryBlocks {
+    // Follows the operations that open and close try blocks, giving what's wrong if anything.
+    pub fn track(&mut self, operation: &str, location: (usize, usize)) -> Result<(), &'static str> {
+        if operation == "try_begin" || operation.starts_with("try_for_") {
+            self.open.push((operation.to_string(), location));
+        }
+        else if operation == "try_end" && self.open.pop().is_none() {
+            return Err("try_end without a matching try block.");
+        }
+        else if operation == "else_try" && self.open.is_empty() {
+            return Err("else_try can only be used inside a try block.");
+        }
+        Ok(())
+    }
+
+    // Records an operation that can fail, returning whether it's outside of every try block.
+    pub fn fail(&mut self) -> bool {
+        if self.open.is_empty() {
+            self.fails_outside = true;
+        }
+        self.open.is_empty()
+    }
+
+    // Every try block left open, outermost first.
+    pub fn unclosed(&self) -> &[(String, (usize, usize))] {
+        &self.open
+    }
+}
+
+// What a script can fail on, either an operation or a call to a cf_ script.
+pub enum Failure<'a> {
+    Operation(&'a str),
+    Call(&'a str),
+}
+
+// Scripts that can fail part way through are named cf_ by convention, since their callers have
+// to run them inside a try block or be cut short along with them.
+pub fn misnamed_failure(script: &str, failure: &Failure) -> Option<String> {
+    if script.starts_with("cf_") {
+        return None;
+    }
+    Some(match failure {
+        Failure::Call(called) => format!(
+            "'script_{}' is called outside of a try block, so '{}' should be named 'cf_{}'.",
+            called, script, script
+        ),
+        Failure::Operation(operation) => format!(
+            "'{}' can fail outside of a try block, so '{}' should be named 'cf_{}'.",
+            operation, script, script
+        ),
+    })
+}
+
+// Likewise a cf_ script with nothing that can fail outside of a try block is misleading.
+pub fn misnamed_success(script: &str, try_blocks: &TryBlocks) -> Option<String> {
+    if script.starts_with("cf_") && !try_blocks.fails_outside {
+        Some(format!("'{}' can never fail but is named as if it can.", script))
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        misnamed_failure, misnamed_success, Call, CallGraph, Failure, Globals, Locals, TryBlocks,
+    };
+    use crate::parser::registers::{Register, RegisterKind};
+
+    #[test]
+    pub fn locals_test() {
+        let mut locals = Locals::default();
+        locals.read("y", "y", (1, 8));
+        locals.assign("i_1", "i", (2, 5));
+        locals.assign("x", "x", (3, 5));
+        locals.read("x", "x", (4, 8));
+        locals.read("y", "y", (5, 8));
+        let (unused, unassigned) = locals.take_misused();
+        assert_eq!(unused, vec![("i".to_string(), (2, 5))]);
+        assert_eq!(unassigned, vec![("y".to_string(), (1, 8))]);
+        assert_eq!(locals.take_misused(), (vec![], vec![]));
+    }
+
+    #[test]
+    pub fn globals_test() {
+        let mut globals = Globals::default();
+        globals.record("$b".to_string(), "b.mbs", (1, 1), "assign $b 1;", true);
+        globals.record("$a".to_string(), "a.mbs", (4, 1), "eq $a 1;", false);
+        globals.record("$c".to_string(), "a.mbs", (1, 1), "assign $c 1;", true);
+        globals.record("$c".to_string(), "b.mbs", (2, 1), "eq $c 1;", false);
+
+        let single_use = globals.take_single_use();
+        let names: Vec<&str> = single_use.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["$a", "$b"]);
+        assert!(single_use[1].1.assigned);
+        assert_eq!(single_use[1].1.line, "assign $b 1;");
+        assert!(globals.take_single_use().is_empty());
+    }
+
+    #[test]
+    pub fn call_graph_test() {
+        let reg = |code| Register(RegisterKind::Register, code);
+        let call = |script: &str, held| Call {
+            path: "a.mbs".to_string(),
+            location: (1, 1),
+            line: String::new(),
+            script: script.to_string(),
+            held,
+        };
+
+        let mut graph = CallGraph::default();
+        graph.call(Some("outer"), call("middle", vec![reg(63), reg(62)]));
+        graph.call(Some("middle"), call("inner", vec![]));
+        graph.call(None, call("unrelated", vec![reg(63)]));
+        graph.use_register("middle", reg(10));
+        graph.use_register("inner", reg(62));
+        graph.use_register("unrelated", reg(61));
+
+        let clashes = graph.take_clashes();
+        assert_eq!(clashes.len(), 1);
+        assert_eq!(clashes[0].0.script, "middle");
+        assert_eq!(clashes[0].1, vec![reg(62)]);
+        assert!(graph.take_clashes().is_empty());
+    }
+
+    #[test]
+    pub fn try_blocks_test() {
+        let mut try_blocks = TryBlocks::default();
+        assert!(try_blocks.track("else_try", (1, 1)).is_err());
+        assert!(try_blocks.track("try_begin", (2, 1)).is_ok());
+        assert!(try_blocks.track("try_for_range", (3, 1)).is_ok());
+        assert!(try_blocks.track("try_begin", (4, 1)).is_ok());
+        assert!(try_blocks.track("else_try", (5, 1)).is_ok());
+        assert!(try_blocks.track("try_end", (6, 1)).is_ok());
+        assert!(!try_blocks.fail());
+
+        let unclosed: Vec<&str> = try_blocks.unclosed().iter().map(|(o, _)| o.as_str()).collect();
+        assert_eq!(unclosed, vec!["try_begin", "try_for_range"]);
+
+        assert!(try_blocks.track("try_end", (7, 1)).is_ok());
+        assert!(try_blocks.track("try_end", (8, 1)).is_ok());
+        assert!(try_blocks.track("try_end", (9, 1)).is_err());
+        assert!(try_blocks.unclosed().is_empty());
+    }
+
+    #[test]
+    pub fn failure_naming_test() {
+        let mut try_blocks = TryBlocks::default();
+        assert!(misnamed_success("cf_check", &try_blocks).is_some());
+        assert!(misnamed_success("check", &try_blocks).is_none());
+
+        assert!(try_blocks.fail());
+        assert!(misnamed_success("cf_check", &try_blocks).is_none());
+
+        assert!(misnamed_failure("cf_check", &Failure::Operation("eq")).is_none());
+        assert_eq!(
+            misnamed_failure("check", &Failure::Call("cf_inner")).as_deref(),
+            Some(
+                "'script_cf_inner' is called outside of a try block, so 'check' should be named \
+                 'cf_check'."
+            )
+        );
+    }
+}
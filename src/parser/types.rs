@@ -0,0 +1,92 @@
+use std::fmt;
+
+use crate::parser::ast::{StatementParameter, StatementParameterId};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Type {
+    Agent,
+    Faction,
+    Item,
+    Menu,
+    Party,
+    PartyTemplate,
+    Position,
+    Presentation,
+    Quest,
+    Scene,
+    SceneProp,
+    Script,
+    String,
+    Troop,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "agent" => Type::Agent,
+            "faction" => Type::Faction,
+            "item" => Type::Item,
+            "menu" => Type::Menu,
+            "party" => Type::Party,
+            "party_template" => Type::PartyTemplate,
+            "position" => Type::Position,
+            "presentation" => Type::Presentation,
+            "quest" => Type::Quest,
+            "scene" => Type::Scene,
+            "scene_prop" => Type::SceneProp,
+            "script" => Type::Script,
+            "string" => Type::String,
+            "troop" => Type::Troop,
+            _ => return None,
+        })
+    }
+
+    pub fn of_id(id: &StatementParameterId) -> Option<Type> {
+        Some(match id {
+            StatementParameterId::FactionId(_) => Type::Faction,
+            StatementParameterId::ItemId(_) => Type::Item,
+            StatementParameterId::GameMenuId(_) => Type::Menu,
+            StatementParameterId::PartyId(_) => Type::Party,
+            StatementParameterId::PartyTemplateId(_) => Type::PartyTemplate,
+            StatementParameterId::PresentationId(_) => Type::Presentation,
+            StatementParameterId::QuestId(_) => Type::Quest,
+            StatementParameterId::ScenePropId(_) => Type::SceneProp,
+            StatementParameterId::SceneId(_) => Type::Scene,
+            StatementParameterId::ScriptId(_) => Type::Script,
+            StatementParameterId::TroopId(_) => Type::Troop,
+            _ => return None,
+        })
+    }
+
+    // Locals are looked up by the caller since their types depend on scope.
+    pub fn of_literal(parameter: &StatementParameter) -> Option<Type> {
+        match parameter {
+            StatementParameter::StringRegister(_) => Some(Type::String),
+            StatementParameter::PositionRegister(_) => Some(Type::Position),
+            StatementParameter::StatementParamId(ref id) => Type::of_id(id),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Agent => "agent",
+            Type::Faction => "faction",
+            Type::Item => "item",
+            Type::Menu => "menu",
+            Type::Party => "party",
+            Type::PartyTemplate => "party_template",
+            Type::Position => "position",
+            Type::Presentation => "presentation",
+            Type::Quest => "quest",
+            Type::Scene => "scene",
+            Type::SceneProp => "scene_prop",
+            Type::Script => "script",
+            Type::String => "string",
+            Type::Troop => "troop",
+        };
+        write!(f, "{}", name)
+    }
+}